@@ -0,0 +1,568 @@
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::{
+	ext::IdentExt, parse_macro_input, parse_quote, spanned::Spanned, Attribute, Data, DeriveInput,
+	Expr, Fields, GenericArgument, Generics, Ident, LitByteStr, PathArguments, Type,
+};
+
+#[proc_macro_derive(BinRead, attributes(bin))]
+pub fn derive_bin_read(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	expand_read(input)
+		.unwrap_or_else(syn::Error::into_compile_error)
+		.into()
+}
+
+#[proc_macro_derive(BinWrite, attributes(bin))]
+pub fn derive_bin_write(input: TokenStream) -> TokenStream {
+	let input = parse_macro_input!(input as DeriveInput);
+	expand_write(input)
+		.unwrap_or_else(syn::Error::into_compile_error)
+		.into()
+}
+
+#[derive(Default)]
+struct Attrs {
+	seen: Vec<(&'static str, Span)>,
+	big_endian: Option<bool>,
+	magic: Option<LitByteStr>,
+	repr: Option<Type>,
+	id: Option<Expr>,
+	pointer: bool,
+	align: Option<Expr>,
+	count: Option<Expr>,
+	calc: Option<Expr>,
+}
+
+impl Attrs {
+	fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+		let mut out = Self::default();
+		for attr in attrs.iter().filter(|attr| attr.path().is_ident("bin")) {
+			attr.parse_nested_meta(|meta| {
+				let name = if meta.path.is_ident("big") {
+					out.big_endian = Some(true);
+					"big"
+				} else if meta.path.is_ident("little") {
+					out.big_endian = Some(false);
+					"little"
+				} else if meta.path.is_ident("magic") {
+					out.magic = Some(meta.value()?.parse()?);
+					"magic"
+				} else if meta.path.is_ident("repr") {
+					out.repr = Some(meta.value()?.parse()?);
+					"repr"
+				} else if meta.path.is_ident("id") {
+					out.id = Some(meta.value()?.parse()?);
+					"id"
+				} else if meta.path.is_ident("pointer") {
+					out.pointer = true;
+					"pointer"
+				} else if meta.path.is_ident("align") {
+					out.align = Some(meta.value()?.parse()?);
+					"align"
+				} else if meta.path.is_ident("count") {
+					out.count = Some(meta.value()?.parse()?);
+					"count"
+				} else if meta.path.is_ident("calc") {
+					out.calc = Some(meta.value()?.parse()?);
+					"calc"
+				} else {
+					return Err(meta.error("unknown bin attribute"));
+				};
+				out.seen.push((name, meta.path.span()));
+				Ok(())
+			})?;
+		}
+		Ok(out)
+	}
+
+	fn allow(&self, allowed: &[&str], place: &str) -> syn::Result<()> {
+		match self.seen.iter().find(|(name, _)| !allowed.contains(name)) {
+			Some((name, span)) => Err(syn::Error::new(
+				*span,
+				format!("`{name}` is not allowed on {place}"),
+			)),
+			None => Ok(()),
+		}
+	}
+}
+
+struct FieldInfo {
+	name: String,
+	binding: Ident,
+	ty: Type,
+	// The type stored at the pointer target, or the field type itself
+	target: Type,
+	optional: bool,
+	attrs: Attrs,
+}
+
+fn parse_fields(fields: &Fields) -> syn::Result<Vec<FieldInfo>> {
+	fields
+		.iter()
+		.enumerate()
+		.map(|(i, field)| {
+			let attrs = Attrs::parse(&field.attrs)?;
+			attrs.allow(
+				&["big", "little", "pointer", "align", "count", "calc"],
+				"fields",
+			)?;
+			let (target, optional) = match generic_arg(&field.ty, "Option") {
+				Some(inner) if attrs.pointer => (inner.clone(), true),
+				_ => (field.ty.clone(), false),
+			};
+			if attrs.count.is_some() {
+				vec_elem(&target)?;
+			}
+			let (name, binding) = match &field.ident {
+				Some(ident) => (ident.unraw().to_string(), ident.clone()),
+				None => (
+					i.to_string(),
+					format_ident!("field_{}", i, span = Span::mixed_site()),
+				),
+			};
+			Ok(FieldInfo {
+				name,
+				binding,
+				ty: field.ty.clone(),
+				target,
+				optional,
+				attrs,
+			})
+		})
+		.collect()
+}
+
+fn generic_arg<'t>(ty: &'t Type, name: &str) -> Option<&'t Type> {
+	if let Type::Path(path) = ty {
+		if let Some(segment) = path.path.segments.last() {
+			if segment.ident == name {
+				if let PathArguments::AngleBracketed(args) = &segment.arguments {
+					if let Some(GenericArgument::Type(elem)) = args.args.first() {
+						return Some(elem);
+					}
+				}
+			}
+		}
+	}
+	None
+}
+
+fn is_type(ty: &Type, name: &str) -> bool {
+	match ty {
+		Type::Path(path) => path.path.is_ident(name),
+		_ => false,
+	}
+}
+
+fn vec_elem(ty: &Type) -> syn::Result<&Type> {
+	generic_arg(ty, "Vec")
+		.ok_or_else(|| syn::Error::new(ty.span(), "`count` requires a `Vec<T>` field"))
+}
+
+fn parser_ident() -> Ident {
+	local("parser")
+}
+
+// Fields are read and written without args, so type parameters need default args too
+fn add_bounds(generics: &Generics, bound: TokenStream2) -> Generics {
+	let mut generics = generics.clone();
+	let params = generics
+		.type_params()
+		.map(|param| param.ident.clone())
+		.collect::<Vec<_>>();
+	for param in generics.type_params_mut() {
+		param.bounds.push(parse_quote!(#bound));
+	}
+	let where_clause = generics.make_where_clause();
+	for param in params {
+		where_clause
+			.predicates
+			.push(parse_quote!(<#param as #bound>::Args: ::std::default::Default));
+	}
+	generics
+}
+
+// Pointer payloads are cloned into a boxed closure, so generic targets must be Clone + 'static
+fn add_pointer_bounds(generics: &mut Generics, infos: &[FieldInfo]) {
+	let params = generics
+		.type_params()
+		.map(|param| param.ident.clone())
+		.collect::<Vec<_>>();
+	let where_clause = generics.make_where_clause();
+	for info in infos {
+		let is_string = info.attrs.count.is_none() && is_type(&info.target, "String");
+		if !info.attrs.pointer || is_string || !mentions(info.target.to_token_stream(), &params) {
+			continue;
+		}
+		let target = &info.target;
+		where_clause
+			.predicates
+			.push(parse_quote!(#target: ::std::clone::Clone + 'static));
+	}
+}
+
+fn mentions(tokens: TokenStream2, idents: &[Ident]) -> bool {
+	tokens.into_iter().any(|token| match token {
+		TokenTree::Ident(ident) => idents.contains(&ident),
+		TokenTree::Group(group) => mentions(group.stream(), idents),
+		_ => false,
+	})
+}
+
+fn local(name: &str) -> Ident {
+	Ident::new(name, Span::mixed_site())
+}
+
+// Wraps an expression evaluating to `Result<T>`
+fn with_endian(big_endian: Option<bool>, body: TokenStream2) -> TokenStream2 {
+	let parser = parser_ident();
+	match big_endian {
+		Some(big_endian) => {
+			let endian = match big_endian {
+				true => quote!(::binary_parser::Endian::Big),
+				false => quote!(::binary_parser::Endian::Little),
+			};
+			quote!(#parser.with_endian(#endian, |#parser| #body))
+		}
+		None => body,
+	}
+}
+
+fn read_magic(magic: &Option<LitByteStr>) -> TokenStream2 {
+	let parser = parser_ident();
+	match magic {
+		Some(magic) => quote!(#parser.expect_magic(#magic)?;),
+		None => quote!(),
+	}
+}
+
+fn write_magic(magic: &Option<LitByteStr>) -> TokenStream2 {
+	let parser = parser_ident();
+	match magic {
+		Some(magic) => quote!(#parser.write_buf(#magic)?;),
+		None => quote!(),
+	}
+}
+
+fn read_fields(fields: &[FieldInfo], big_endian: Option<bool>) -> TokenStream2 {
+	let parser = parser_ident();
+	fields
+		.iter()
+		.map(|field| {
+			let FieldInfo {
+				name,
+				binding,
+				ty,
+				target,
+				optional,
+				attrs,
+			} = field;
+			// Each step evaluates to a Result, so generated code has no Ok(..?) for clippy to flag
+			let mut value = match &attrs.count {
+				Some(expr) => quote! {
+					<#target as ::binary_parser::BinRead>::read_args(
+						#parser,
+						::binary_parser::Count((#expr) as usize),
+					)
+				},
+				None => quote!(<#target as ::binary_parser::BinRead>::read(#parser)),
+			};
+			if *optional {
+				value = quote!(#parser.read_optional_pointer(|#parser| #value));
+			} else if attrs.pointer {
+				value = quote!(#parser.read_pointer(|#parser| #value));
+			}
+			if let Some(big_endian) = attrs.big_endian.or(big_endian) {
+				value = with_endian(Some(big_endian), value);
+			}
+			let align = attrs
+				.align
+				.as_ref()
+				.map(|align| quote!(#parser.align_seek((#align) as u64)?;));
+			quote! {
+				let #binding: #ty = #parser.context(#name, |#parser| {
+					#align
+					#value
+				})?;
+			}
+		})
+		.collect()
+}
+
+fn write_fields(fields: &[FieldInfo], big_endian: Option<bool>) -> TokenStream2 {
+	let parser = parser_ident();
+	fields
+		.iter()
+		.map(|field| {
+			let FieldInfo {
+				binding,
+				ty,
+				target,
+				optional,
+				attrs,
+				..
+			} = field;
+			let big_endian = attrs.big_endian.or(big_endian);
+			let value = local("value");
+			let write =
+				|value: TokenStream2| quote!(::binary_parser::BinWrite::write(#value, #parser)?;);
+			let is_string = attrs.count.is_none() && is_type(target, "String");
+			let mut value = if attrs.pointer && is_string {
+				// Goes through the string API so the parser's string pool applies
+				if *optional {
+					quote! {
+						match #binding {
+							Some(#value) => #parser.write_null_string_pointer(#value)?,
+							None => #parser.write_null_pointer()?,
+						}
+					}
+				} else {
+					quote!(#parser.write_null_string_pointer(#binding)?;)
+				}
+			} else if attrs.pointer {
+				let write = write(quote!(&#value));
+				let payload = with_endian(big_endian, quote!({ #write Ok(()) }));
+				if *optional {
+					quote! {
+						let #value = ::std::clone::Clone::clone(#binding);
+						#parser.write_optional_pointer(#value, move |#parser, #value| #payload)?;
+					}
+				} else {
+					quote! {
+						let #value = ::std::clone::Clone::clone(#binding);
+						#parser.write_pointer(move |#parser| #payload)?;
+					}
+				}
+			} else {
+				write(quote!(#binding))
+			};
+			if big_endian.is_some() {
+				value = with_endian(big_endian, quote!({ #value Ok(()) }));
+				value = quote!(#value?;);
+			}
+			let align = attrs
+				.align
+				.as_ref()
+				.map(|align| quote!(#parser.align_write((#align) as u64)?;));
+			let calc = attrs
+				.calc
+				.as_ref()
+				.map(|calc| quote!(let #binding: &#ty = &(#calc);));
+			quote! {
+				#calc
+				#align
+				#value
+			}
+		})
+		.collect()
+}
+
+fn construct(path: TokenStream2, fields: &Fields, infos: &[FieldInfo]) -> TokenStream2 {
+	let bindings = infos.iter().map(|field| &field.binding);
+	match fields {
+		Fields::Named(_) => quote!(#path { #(#bindings),* }),
+		Fields::Unnamed(_) => quote!(#path ( #(#bindings),* )),
+		Fields::Unit => quote!(#path),
+	}
+}
+
+struct Variant {
+	ident: Ident,
+	id: TokenStream2,
+	fields: Fields,
+	infos: Vec<FieldInfo>,
+}
+
+fn parse_variants(input: &DeriveInput, attrs: &Attrs) -> syn::Result<Vec<Variant>> {
+	let Data::Enum(data) = &input.data else {
+		unreachable!()
+	};
+	let Some(repr) = &attrs.repr else {
+		return Err(syn::Error::new(
+			input.ident.span(),
+			"enums require `#[bin(repr = <integer type>)]`",
+		));
+	};
+	let mut previous: Option<TokenStream2> = None;
+	data.variants
+		.iter()
+		.map(|variant| {
+			let variant_attrs = Attrs::parse(&variant.attrs)?;
+			variant_attrs.allow(&["id"], "variants")?;
+			let id = match (&variant_attrs.id, &variant.discriminant) {
+				(Some(id), _) | (None, Some((_, id))) => quote!((#id) as #repr),
+				(None, None) => match &previous {
+					Some(previous) => quote!(#previous + 1),
+					None => quote!(0 as #repr),
+				},
+			};
+			previous = Some(id.clone());
+			Ok(Variant {
+				ident: variant.ident.clone(),
+				id,
+				fields: variant.fields.clone(),
+				infos: parse_fields(&variant.fields)?,
+			})
+		})
+		.collect()
+}
+
+fn expand_read(input: DeriveInput) -> syn::Result<TokenStream2> {
+	let parser = parser_ident();
+	let attrs = Attrs::parse(&input.attrs)?;
+	let body = match &input.data {
+		Data::Struct(data) => {
+			attrs.allow(&["big", "little", "magic"], "structs")?;
+			let infos = parse_fields(&data.fields)?;
+			let magic = read_magic(&attrs.magic);
+			let fields = read_fields(&infos, attrs.big_endian);
+			let construct = construct(quote!(Self), &data.fields, &infos);
+			quote! {{
+				#magic
+				#fields
+				Ok(#construct)
+			}}
+		}
+		Data::Enum(_) => {
+			attrs.allow(&["big", "little", "magic", "repr"], "enums")?;
+			let repr = attrs.repr.as_ref();
+			let variants = parse_variants(&input, &attrs)?;
+			let magic = read_magic(&attrs.magic);
+			let id = local("id");
+			let arms = variants.iter().map(|variant| {
+				let Variant {
+					ident,
+					id: expected,
+					fields,
+					infos,
+				} = variant;
+				let read = read_fields(infos, attrs.big_endian);
+				let construct = construct(quote!(Self::#ident), fields, infos);
+				quote! {
+					if #id == #expected {
+						#read
+						return Ok(#construct);
+					}
+				}
+			});
+			let name = input.ident.to_string();
+			let pos = local("pos");
+			quote! {{
+				#magic
+				let #pos = #parser.position();
+				let #id = <#repr as ::binary_parser::BinRead>::read(#parser)?;
+				#(#arms)*
+				Err(#parser.error_at(
+					#pos,
+					::binary_parser::BinaryParserError::invalid_value(#name, #id),
+				))
+			}}
+		}
+		Data::Union(_) => {
+			return Err(syn::Error::new(
+				input.ident.span(),
+				"BinRead cannot be derived for unions",
+			))
+		}
+	};
+	let body = with_endian(attrs.big_endian, body);
+
+	let ident = &input.ident;
+	let generics = add_bounds(&input.generics, quote!(::binary_parser::BinRead));
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::binary_parser::BinRead for #ident #ty_generics #where_clause {
+			type Args = ();
+
+			fn read_args<__S: ::std::io::Read + ::std::io::Seek>(
+				#parser: &mut ::binary_parser::BinaryParser<__S>,
+				_: (),
+			) -> ::binary_parser::Result<Self> {
+				#body
+			}
+		}
+	})
+}
+
+fn expand_write(input: DeriveInput) -> syn::Result<TokenStream2> {
+	let parser = parser_ident();
+	let attrs = Attrs::parse(&input.attrs)?;
+	let mut generics = add_bounds(&input.generics, quote!(::binary_parser::BinWrite));
+	let body = match &input.data {
+		Data::Struct(data) => {
+			attrs.allow(&["big", "little", "magic"], "structs")?;
+			let infos = parse_fields(&data.fields)?;
+			add_pointer_bounds(&mut generics, &infos);
+			let magic = write_magic(&attrs.magic);
+			let fields = write_fields(&infos, attrs.big_endian);
+			let destructure = construct(quote!(Self), &data.fields, &infos);
+			quote! {{
+				#[allow(unused_variables)]
+				let #destructure = self;
+				#magic
+				#fields
+				Ok(())
+			}}
+		}
+		Data::Enum(_) => {
+			attrs.allow(&["big", "little", "magic", "repr"], "enums")?;
+			let repr = attrs.repr.as_ref();
+			let variants = parse_variants(&input, &attrs)?;
+			for variant in &variants {
+				add_pointer_bounds(&mut generics, &variant.infos);
+			}
+			let magic = write_magic(&attrs.magic);
+			let id = local("id");
+			let arms = variants.iter().map(|variant| {
+				let Variant {
+					ident,
+					id: expected,
+					fields,
+					infos,
+				} = variant;
+				let write = write_fields(infos, attrs.big_endian);
+				let destructure = construct(quote!(Self::#ident), fields, infos);
+				quote! {
+					#[allow(unused_variables)]
+					#destructure => {
+						let #id: #repr = #expected;
+						::binary_parser::BinWrite::write(&#id, #parser)?;
+						#write
+					}
+				}
+			});
+			quote! {{
+				#magic
+				match self {
+					#(#arms)*
+				}
+				Ok(())
+			}}
+		}
+		Data::Union(_) => {
+			return Err(syn::Error::new(
+				input.ident.span(),
+				"BinWrite cannot be derived for unions",
+			))
+		}
+	};
+	let body = with_endian(attrs.big_endian, body);
+
+	let ident = &input.ident;
+	let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+	Ok(quote! {
+		impl #impl_generics ::binary_parser::BinWrite for #ident #ty_generics #where_clause {
+			type Args = ();
+
+			fn write_args<__S: ::std::io::Write + ::std::io::Seek>(
+				&self,
+				#parser: &mut ::binary_parser::BinaryParser<__S>,
+				_: (),
+			) -> ::binary_parser::Result<()> {
+				#body
+			}
+		}
+	})
+}
@@ -0,0 +1,106 @@
+use crate::{BinaryParser, BinaryParserError, Result, Width};
+use std::io::{Read, Seek, SeekFrom};
+
+// Header layout of length prefixed chunks, a magic followed by a size field
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ChunkLayout {
+	pub(crate) magic_len: usize,
+	pub(crate) size_width: Width,
+	pub(crate) size_includes_header: bool,
+	pub(crate) alignment: u64,
+}
+
+impl ChunkLayout {
+	pub fn new(magic_len: usize, size_width: Width) -> Self {
+		Self {
+			magic_len,
+			size_width,
+			size_includes_header: false,
+			alignment: 1,
+		}
+	}
+
+	// Counts the magic and size field in the size
+	pub fn size_includes_header(mut self, include: bool) -> Self {
+		self.size_includes_header = include;
+		self
+	}
+
+	// Pads the end of each chunk, the padding is counted in the size
+	pub fn align(mut self, alignment: u64) -> Self {
+		self.alignment = alignment;
+		self
+	}
+}
+
+// Header of a chunk, its body is read in place through Chunks::view or BinaryParser::sub_view
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Chunk {
+	pub magic: Vec<u8>,
+	// The size field as stored
+	pub size: u64,
+	// Position of the chunk header
+	pub offset: u64,
+	// Position and length of the body, without the alignment padding that follows it
+	pub body: u64,
+	pub body_len: u64,
+}
+
+// Iterator over consecutive chunks until the end of the stream, see BinaryParser::chunks
+pub struct Chunks<'p, 'a, S> {
+	pub(crate) parser: &'p mut BinaryParser<'a, S>,
+	pub(crate) layout: ChunkLayout,
+	pub(crate) done: bool,
+}
+
+impl<'p, 'a, S: Read + Seek> Chunks<'p, 'a, S> {
+	// Runs func on a view bounded to the chunk body, positions in it stay absolute
+	pub fn view<T, F>(&mut self, chunk: &Chunk, func: F) -> Result<T>
+	where
+		F: FnOnce(&mut BinaryParser<'a, &mut S>) -> Result<T>,
+	{
+		self.parser.sub_view(chunk.body, chunk.body_len, func)
+	}
+
+	fn read_chunk(&mut self) -> Result<Chunk> {
+		let parser = &mut *self.parser;
+		let layout = self.layout;
+		let offset = parser.position();
+		let magic = parser.read_buf(layout.magic_len)?;
+		let size = parser.read_pointer_value(layout.size_width)?;
+		let header = layout.magic_len as u64 + layout.size_width.bytes();
+		let body_len = match layout.size_includes_header {
+			true => size.checked_sub(header).ok_or_else(|| {
+				parser.error_at(offset, BinaryParserError::invalid_value("chunk size", size))
+			})?,
+			false => size,
+		};
+		let body = parser.position();
+		let available = parser.len().saturating_sub(body);
+		if body_len > available {
+			return Err(parser.eof(body_len as usize, available as usize));
+		}
+		parser.seek(SeekFrom::Start(body + body_len))?;
+		parser.align_seek(layout.alignment)?;
+		Ok(Chunk {
+			magic,
+			size,
+			offset,
+			body,
+			body_len,
+		})
+	}
+}
+
+impl<'p, 'a, S: Read + Seek> Iterator for Chunks<'p, 'a, S> {
+	type Item = Result<Chunk>;
+
+	fn next(&mut self) -> Option<Self::Item> {
+		if self.done || self.parser.position() >= self.parser.len() {
+			return None;
+		}
+		let res = self.read_chunk();
+		self.done = res.is_err();
+		Some(res)
+	}
+}
@@ -1,40 +1,337 @@
 use std::{
-	collections::VecDeque,
+	borrow::Cow,
+	collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+	fs::File,
 	io::{self, *},
 	path::Path,
 };
 use thiserror::Error;
 
+mod chunk;
+mod pointer;
+pub use chunk::*;
+pub use pointer::*;
+
 pub type Result<T> = std::result::Result<T, BinaryParserError>;
 
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Endian {
+	Big,
+	#[default]
+	Little,
+	// Resolved to Big or Little for the target when applied
+	Native,
+}
+
+impl Endian {
+	pub fn is_big(self) -> bool {
+		match self {
+			Self::Big => true,
+			Self::Little => false,
+			Self::Native => cfg!(target_endian = "big"),
+		}
+	}
+}
+
 #[derive(Error, Debug)]
 pub enum BinaryParserError {
-	#[error("IO error")]
+	#[error("IO error: {0}")]
 	Io(#[from] io::Error),
-	#[error("UTF8 parse error")]
+	#[error("UTF8 parse error: {0}")]
 	Utf8(#[from] std::str::Utf8Error),
+	#[error("Unexpected EOF, wanted {wanted} bytes but {available} available")]
+	UnexpectedEof {
+		pos: u64,
+		wanted: u64,
+		available: u64,
+	},
+	#[error("Bad magic, expected {expected:02x?} but found {found:02x?}")]
+	BadMagic {
+		expected: Vec<u8>,
+		found: Vec<u8>,
+		pos: u64,
+	},
+	#[error("Invalid value {value} for {name}")]
+	InvalidValue { name: String, value: String },
+	#[error("Pointer {pointer:#x} is out of bounds for length {len:#x}")]
+	PointerOutOfBounds { pointer: u64, len: u64 },
+	#[error("Pointer {pointer:#x} does not fit in {width:?}")]
+	PointerOverflow { pointer: u64, width: Width },
+	#[error("Pointer target {target:#x} is before its origin {origin:#x}")]
+	NegativePointer { target: u64, origin: u64 },
+	#[error("Value {value:#x} does not fit in {width:?}")]
+	ValueOverflow { value: u64, width: Width },
+	#[error("Reserved {width:?} slot was never filled")]
+	UnfilledSlot { width: Width },
+	#[error("Seek before the start at {start:#x}")]
+	SeekBeforeStart { start: u64 },
+	#[error(
+		"Sub view ended with {writes} pointer writes, {slots} slots and {chunks} chunks pending"
+	)]
+	PendingInView {
+		writes: usize,
+		slots: usize,
+		chunks: usize,
+	},
+	#[error("Neither byte order matched")]
+	UnknownEndian,
+	#[error("end_chunk called without an open chunk")]
+	MissingChunk,
+	#[error("No marker pushed for a marker relative pointer")]
+	MissingMarker,
+	#[error("{0}")]
+	Custom(String),
+	#[error("{error} at {pos:#x}{}", fmt_path(.path))]
+	Context {
+		pos: u64,
+		path: Vec<String>,
+		error: Box<BinaryParserError>,
+	},
+}
+
+fn fmt_path(path: &[String]) -> String {
+	if path.is_empty() {
+		String::new()
+	} else {
+		format!(" in {}", path.join(" > "))
+	}
+}
+
+impl BinaryParserError {
+	pub fn custom<T: std::fmt::Display>(msg: T) -> Self {
+		Self::Custom(msg.to_string())
+	}
+
+	pub fn invalid_value<N: Into<String>, V: std::fmt::Display>(name: N, value: V) -> Self {
+		Self::InvalidValue {
+			name: name.into(),
+			value: value.to_string(),
+		}
+	}
+
+	// Strips the position and path added by the parser
+	pub fn inner(&self) -> &Self {
+		match self {
+			Self::Context { error, .. } => error.inner(),
+			error => error,
+		}
+	}
+
+	pub fn position(&self) -> Option<u64> {
+		match self {
+			Self::Context { pos, .. }
+			| Self::UnexpectedEof { pos, .. }
+			| Self::BadMagic { pos, .. } => Some(*pos),
+			_ => None,
+		}
+	}
+
+	pub fn path(&self) -> &[String] {
+		match self {
+			Self::Context { path, .. } => path,
+			_ => &[],
+		}
+	}
+}
+
+#[cfg(feature = "derive")]
+pub use binary_parser_derive::{BinRead, BinWrite};
+
+pub struct BinaryParser<'a, S = Cursor<Vec<u8>>> {
+	inner: S,
+	position: u64,
+	len: u64,
+	// Lower bound for seeks, set for sub views
+	start: u64,
+	// Shrinks the stream to len after finish_writes dropped a duplicate payload
+	truncate: Option<fn(&mut S, u64) -> io::Result<()>>,
+	// Collects written bytes while a dedup payload is being written
+	capture: Option<Vec<u8>>,
+	// Deferred writes grouped by section in layout order, the unnamed section comes first
+	sections: Vec<Section<'a, S>>,
+	string_writes: VecDeque<ScheduledWrite<'a, S>>,
+	string_pool: StringPool,
+	write_order: WriteOrder,
+	// Nesting level of the payload finish_writes is running
+	depth: u32,
+	// Pointer positions patched by finish_writes, relative to the base each was written under
+	relocations: Vec<u64>,
+	relocation_table: Option<RelocationFn<'a, S>>,
+	// Absolute positions known to hold pointers, and the pointer fields read so far
+	marked_pointers: BTreeSet<u64>,
+	visited_pointers: HashSet<u64>,
+	// Reserved placeholders not filled yet
+	slots: BTreeMap<u64, Width>,
+	chunks: Vec<OpenChunk>,
+	big_endian: bool,
+	pointer_width: Width,
+	relative_to: RelativeTo,
+	null_pointer: u64,
+	markers: Vec<u64>,
+	bases: Vec<u64>,
+	contexts: Vec<Cow<'static, str>>,
+}
+
+type WriteFn<'a, S> = Box<dyn FnOnce(&mut BinaryParser<'a, S>) -> Result<()> + 'a>;
+type RelocationFn<'a, S> = Box<dyn FnOnce(&mut BinaryParser<'a, S>, &[u64]) -> Result<()> + 'a>;
+
+struct Section<'a, S> {
+	name: Cow<'static, str>,
+	alignment: u64,
+	writes: VecDeque<ScheduledWrite<'a, S>>,
+}
+
+impl<'a, S> Section<'a, S> {
+	fn new(name: Cow<'static, str>, alignment: u64) -> Self {
+		Self {
+			name,
+			alignment,
+			writes: VecDeque::new(),
+		}
+	}
+}
+
+struct ScheduledWrite<'a, S> {
+	func: WriteFn<'a, S>,
+	field: PointerField,
+	base: u64,
+	// Scheduled with no base pushed, so base is the stream start
+	unbased: bool,
+	dedup: Option<Dedup>,
+	priority: i32,
+	depth: u32,
+	align: Option<(u64, u8)>,
+	// Payload of string pointers, so merged parsers can pool them
+	string: Option<Vec<u8>>,
+	// Byte order the write was scheduled under, the payload is written in it
+	big_endian: bool,
+}
+
+// Keeps the queue sorted by priority, and by depth first when depth_first is set
+fn enqueue<'a, S>(
+	queue: &mut VecDeque<ScheduledWrite<'a, S>>,
+	write: ScheduledWrite<'a, S>,
+	depth_first: bool,
+) {
+	let key = |write: &ScheduledWrite<'a, S>| (write.priority, depth_first.then_some(write.depth));
+	let index = queue.partition_point(|queued| key(queued) >= key(&write));
+	queue.insert(index, write);
 }
 
-#[derive(Default)]
-pub struct BinaryParser<'a> {
-	inner: Cursor<Vec<u8>>,
-	scheduled_writes: VecDeque<ScheduledWrite<'a>>,
+// Placeholder written by reserve_*, patched later with fill
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Slot {
+	position: u64,
+	width: Width,
 	big_endian: bool,
 }
 
-struct ScheduledWrite<'a> {
-	func: Box<dyn FnOnce(&mut BinaryParser<'a>) -> Result<()> + 'a>,
+impl Slot {
+	pub fn position(&self) -> u64 {
+		self.position
+	}
+
+	pub fn width(&self) -> Width {
+		self.width
+	}
+}
+
+struct OpenChunk {
+	start: u64,
+	size: Slot,
+	layout: ChunkLayout,
+}
+
+#[derive(Clone, Copy)]
+struct PointerField {
 	position: u64,
-	offset: u64,
+	origin: u64,
+	width: Width,
+	// The origin is the stream start rather than a position in it
+	from_start: bool,
+	big_endian: bool,
+}
+
+pub trait BinRead: Sized {
+	// Extra input needed to read the type, e.g. Count for Vec
+	type Args;
+
+	fn read_args<S: Read + Seek>(parser: &mut BinaryParser<S>, args: Self::Args) -> Result<Self>;
+
+	fn read<S: Read + Seek>(parser: &mut BinaryParser<S>) -> Result<Self>
+	where
+		Self::Args: Default,
+	{
+		Self::read_args(parser, Self::Args::default())
+	}
+}
+
+pub trait BinWrite {
+	type Args;
+
+	fn write_args<S: Write + Seek>(
+		&self,
+		parser: &mut BinaryParser<S>,
+		args: Self::Args,
+	) -> Result<()>;
+
+	fn write<S: Write + Seek>(&self, parser: &mut BinaryParser<S>) -> Result<()>
+	where
+		Self::Args: Default,
+	{
+		self.write_args(parser, Self::Args::default())
+	}
+}
+
+// Number of elements to read for Vec
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Count(pub usize);
+
+// Layout of String for BinRead and str / String for BinWrite
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StringArgs {
+	#[default]
+	NullTerminated,
+	// Exactly this many bytes, null padded when writing and cut at the first null when reading
+	Fixed(usize),
+	// A null terminated string behind a pointer
+	Pointer,
+}
+
+// Layout of Option<T> for BinRead and BinWrite, holding the args of T
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum OptionArgs<A> {
+	// Missing at the end of the stream, None writes nothing so only the last field can be None
+	Trailing(A),
+	// Behind a pointer, None is the null pointer like read_optional_pointer
+	Pointer(A),
+}
+
+impl<A: Default> Default for OptionArgs<A> {
+	fn default() -> Self {
+		Self::Trailing(A::default())
+	}
 }
 
 macro_rules! int_impl {
-	($ty: ty, $bytes: literal) => {
+	(read, $ty: ty, $bytes: literal) => {
 		paste::item! {
 			pub fn [< read_ $ty >] (&mut self) -> Result<$ty> {
+				self.[< read_ $ty _endian >](self.big_endian)
+			}
+
+			pub fn [< read_ $ty _be >] (&mut self) -> Result<$ty> {
+				self.[< read_ $ty _endian >](true)
+			}
+
+			pub fn [< read_ $ty _le >] (&mut self) -> Result<$ty> {
+				self.[< read_ $ty _endian >](false)
+			}
+
+			fn [< read_ $ty _endian >] (&mut self, big_endian: bool) -> Result<$ty> {
 				let mut buf: [u8; $bytes] = [0; $bytes];
-				self.inner.read_exact(&mut buf)?;
-				let val = if self.big_endian {
+				self.read_exact(&mut buf)?;
+				let val = if big_endian {
 					$ty::from_be_bytes(buf)
 				} else {
 					$ty::from_le_bytes(buf)
@@ -45,36 +342,39 @@ macro_rules! int_impl {
 			pub fn [< read_ $ty _array >] (&mut self, count: u64) -> Result<Vec<$ty>> {
 				let mut data = vec![];
 				for _ in 0..count {
-					let mut buf: [u8; $bytes] = [0; $bytes];
-					self.inner.read_exact(&mut buf)?;
-					let val = if self.big_endian {
-						$ty::from_be_bytes(buf)
-					} else {
-						$ty::from_le_bytes(buf)
-					};
-					data.push(val);
+					data.push(self.[< read_ $ty >]()?);
 				}
 				Ok(data)
 			}
-
+		}
+	};
+	(write, $ty: ty, $bytes: literal) => {
+		paste::item! {
 			pub fn [< write_ $ty >] (&mut self, data: $ty) -> Result<()> {
-				let buf = if self.big_endian {
+				self.[< write_ $ty _endian >](data, self.big_endian)
+			}
+
+			pub fn [< write_ $ty _be >] (&mut self, data: $ty) -> Result<()> {
+				self.[< write_ $ty _endian >](data, true)
+			}
+
+			pub fn [< write_ $ty _le >] (&mut self, data: $ty) -> Result<()> {
+				self.[< write_ $ty _endian >](data, false)
+			}
+
+			fn [< write_ $ty _endian >] (&mut self, data: $ty, big_endian: bool) -> Result<()> {
+				let buf = if big_endian {
 					$ty::to_be_bytes(data)
 				} else {
 					$ty::to_le_bytes(data)
 				};
-				self.inner.write_all(&buf)?;
+				self.write_all(&buf)?;
 				Ok(())
 			}
 
 			pub fn [< write_ $ty _array >] (&mut self, data: &[$ty]) -> Result<()> {
 				for elem in data {
-					let buf = if self.big_endian {
-						$ty::to_be_bytes(*elem)
-					} else {
-						$ty::to_le_bytes(*elem)
-					};
-					self.inner.write_all(&buf)?;
+					self.[< write_ $ty >](*elem)?;
 				}
 				Ok(())
 			}
@@ -82,39 +382,36 @@ macro_rules! int_impl {
 	};
 }
 
+impl<'a> Default for BinaryParser<'a> {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl<'a> BinaryParser<'a> {
 	pub fn new() -> Self {
-		Self {
-			inner: Cursor::new(vec![]),
-			scheduled_writes: VecDeque::new(),
-			big_endian: false,
-		}
+		Self::from_buf(vec![])
 	}
 
 	pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
 		let buf = std::fs::read(path)?;
-		Ok(Self {
-			inner: Cursor::new(buf),
-			scheduled_writes: VecDeque::new(),
-			big_endian: false,
-		})
+		Ok(Self::from_buf(buf))
 	}
 
 	pub fn from_buf<B: Into<Vec<u8>>>(buf: B) -> Self {
-		Self {
-			inner: Cursor::new(buf.into()),
-			scheduled_writes: VecDeque::new(),
-			big_endian: false,
-		}
-	}
-
-	pub fn set_big_endian(&mut self, be: bool) {
-		self.big_endian = be;
+		let buf = buf.into();
+		let len = buf.len() as u64;
+		let mut parser = Self::from_inner(Cursor::new(buf), 0, len);
+		parser.truncate = Some(|inner, len| {
+			inner.get_mut().truncate(len as usize);
+			Ok(())
+		});
+		parser
 	}
 
 	pub fn to_file<P: AsRef<Path>>(self, path: P) -> Result<()> {
 		let parser = self.finish_writes()?;
-		let mut file = std::fs::File::create(path)?;
+		let mut file = File::create(path)?;
 		file.write_all(parser.inner.get_ref())?;
 		Ok(())
 	}
@@ -133,187 +430,2006 @@ impl<'a> BinaryParser<'a> {
 		}
 	}
 
-	pub fn position(&self) -> u64 {
-		self.inner.position()
+	// Splices in parser without resolving its deferred writes, they join this parser's queues
+	// as if parser had been written here directly. Pointers relative to its start resolve
+	// against the current base, and its string pointers follow this parser's string pool
+	pub fn write_parser_merged(&mut self, parser: BinaryParser<'a>) -> Result<()> {
+		let start = self.position();
+		let base = self.base();
+		let unbased = self.bases.is_empty();
+		let depth = self.depth;
+		let depth_first = self.write_order == WriteOrder::DepthFirst;
+		let rebase = |mut write: ScheduledWrite<'a, Cursor<Vec<u8>>>| {
+			write.field.position += start;
+			write.field.origin += if write.field.from_start { base } else { start };
+			write.field.from_start &= unbased;
+			write.base = if write.unbased {
+				base
+			} else {
+				write.base + start
+			};
+			write.unbased &= unbased;
+			write.depth = depth;
+			write
+		};
+
+		let mut parser = parser;
+		let len = parser.len as usize;
+		self.write_all(&parser.inner.get_ref()[..len])?;
+		for section in std::mem::take(&mut parser.sections) {
+			let count = self.sections.len();
+			let index = self.section_index(section.name);
+			if index == count {
+				self.sections[index].alignment = section.alignment;
+			}
+			for write in section.writes {
+				self.merge_write(rebase(write), index, depth_first);
+			}
+		}
+		let index = self.section_index(Cow::Borrowed(""));
+		for write in std::mem::take(&mut parser.string_writes) {
+			self.merge_write(rebase(write), index, depth_first);
+		}
+		let slots = parser
+			.slots
+			.iter()
+			.map(|(pos, width)| (pos + start, *width));
+		self.slots.extend(slots);
+		Ok(())
 	}
+}
 
-	int_impl!(u8, 1);
-	int_impl!(u16, 2);
-	int_impl!(u32, 4);
-	int_impl!(u64, 8);
-	int_impl!(i8, 1);
-	int_impl!(i16, 2);
-	int_impl!(i32, 4);
-	int_impl!(i64, 8);
-	int_impl!(f32, 4);
-	int_impl!(f64, 8);
+impl<'a, S> BinaryParser<'a, S> {
+	// Queues a write taken from another parser, pooling its strings by this parser's setting
+	fn merge_write(&mut self, write: ScheduledWrite<'a, S>, index: usize, depth_first: bool) {
+		let mut write = write;
+		if let Some(string) = &write.string {
+			let pooled = Dedup::Output(Some(string.clone()));
+			match self.string_pool {
+				StringPool::Disabled => {
+					if write.dedup.as_ref() == Some(&pooled) {
+						write.dedup = None;
+					}
+				}
+				StringPool::Inline | StringPool::Trailing => write.dedup = Some(pooled),
+			}
+			if self.string_pool == StringPool::Trailing {
+				enqueue(&mut self.string_writes, write, depth_first);
+				return;
+			}
+		}
+		enqueue(&mut self.sections[index].writes, write, depth_first);
+	}
+}
 
-	pub fn read_null_string(&mut self) -> Result<String> {
-		let mut buf = vec![];
-		self.inner.read_until(0x00, &mut buf)?;
-		buf.pop();
-		Ok(String::from(std::str::from_utf8(&buf)?))
+impl<'a, 'data> BinaryParser<'a, Cursor<&'data [u8]>> {
+	pub fn from_slice(buf: &'data [u8]) -> Self {
+		Self::from_inner(Cursor::new(buf), 0, buf.len() as u64)
 	}
 
-	pub fn read_string(&mut self, length: usize) -> Result<String> {
-		let mut buf = vec![0; length];
-		self.inner.read_exact(&mut buf)?;
-		Ok(String::from(std::str::from_utf8(&buf)?))
+	fn remaining_slice(&self) -> &'data [u8] {
+		let data: &'data [u8] = self.inner.get_ref();
+		data.get(self.position as usize..self.len as usize)
+			.unwrap_or_default()
 	}
 
-	pub fn read_null_string_pointer(&mut self) -> Result<String> {
-		self.read_pointer(|reader| reader.read_null_string())
+	fn advance(&mut self, length: usize) {
+		self.position += length as u64;
+		self.inner.set_position(self.position);
 	}
 
-	pub fn write_string(&mut self, data: &str) -> Result<()> {
-		let buf = data.as_bytes();
-		self.inner.write_all(buf)?;
-		Ok(())
+	pub fn read_bytes_ref(&mut self, length: usize) -> Result<&'data [u8]> {
+		let buf = self.remaining_slice();
+		let buf = buf
+			.get(..length)
+			.ok_or_else(|| self.eof(length, buf.len()))?;
+		self.advance(length);
+		Ok(buf)
 	}
 
-	pub fn write_null_string(&mut self, data: &str) -> Result<()> {
-		let buf = data.as_bytes();
-		self.inner.write_all(buf)?;
-		self.write_u8(0)?;
-		Ok(())
+	pub fn read_str_ref(&mut self, length: usize) -> Result<&'data str> {
+		let pos = self.position();
+		let buf = self.read_bytes_ref(length)?;
+		std::str::from_utf8(buf).map_err(|err| self.error_at(pos, err))
 	}
 
-	pub fn write_null_string_pointer(&mut self, data: &str) -> Result<()> {
-		let buf = data.as_bytes().to_vec();
-		self.write_pointer(move |writer| {
-			writer.inner.write_all(&buf)?;
-			writer.write_u8(0)?;
-			Ok(())
-		})?;
-		Ok(())
+	pub fn read_null_str_ref(&mut self) -> Result<&'data str> {
+		let data = self.remaining_slice();
+		let (buf, length) = match data.iter().position(|&byte| byte == 0x00) {
+			Some(end) => (&data[..end], end + 1),
+			None => (data, data.len()),
+		};
+		let string = std::str::from_utf8(buf).map_err(|err| self.error(err))?;
+		self.advance(length);
+		Ok(string)
 	}
+}
 
-	pub fn read_buf(&mut self, length: usize) -> Result<Vec<u8>> {
-		let mut buf = vec![0; length];
-		self.inner.read_exact(&mut buf)?;
-		Ok(buf)
+impl<'a> BinaryParser<'a, BufReader<File>> {
+	// Streams reads from the file instead of loading it into memory
+	pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let file = File::open(path)?;
+		let len = file.metadata()?.len();
+		Ok(Self::from_inner(BufReader::new(file), 0, len))
 	}
+}
 
-	pub fn write_buf(&mut self, data: &[u8]) -> Result<()> {
-		self.inner.write_all(data)?;
-		Ok(())
+#[cfg(feature = "mmap")]
+impl<'a> BinaryParser<'a, Cursor<memmap2::Mmap>> {
+	// The file must not be modified while it is mapped
+	pub fn from_mmap<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let file = File::open(path)?;
+		let map = unsafe { memmap2::Mmap::map(&file)? };
+		let len = map.len() as u64;
+		Ok(Self::from_inner(Cursor::new(map), 0, len))
 	}
+}
 
-	pub fn read_parser(&mut self, length: usize) -> Result<Self> {
-		let buf = self.read_buf(length)?;
-		Ok(Self::from_buf(buf))
+impl<'a> BinaryParser<'a, BufWriter<File>> {
+	pub fn create<P: AsRef<Path>>(path: P) -> Result<Self> {
+		let mut parser = Self::from_inner(BufWriter::new(File::create(path)?), 0, 0);
+		parser.truncate = Some(|inner, len| {
+			inner.flush()?;
+			inner.get_ref().set_len(len)
+		});
+		Ok(parser)
 	}
+}
 
-	pub fn write_parser(&mut self, parser: Self) -> Result<()> {
-		let new = parser.to_buf()?;
-		self.write_buf(&new)
+impl<'a, S> BinaryParser<'a, S> {
+	fn from_inner(inner: S, position: u64, len: u64) -> Self {
+		Self {
+			inner,
+			position,
+			len,
+			start: 0,
+			truncate: None,
+			capture: None,
+			sections: vec![Section::new(Cow::Borrowed(""), 1)],
+			string_writes: VecDeque::new(),
+			string_pool: StringPool::Disabled,
+			write_order: WriteOrder::BreadthFirst,
+			depth: 0,
+			relocations: Vec::new(),
+			relocation_table: None,
+			marked_pointers: BTreeSet::new(),
+			visited_pointers: HashSet::new(),
+			slots: BTreeMap::new(),
+			chunks: Vec::new(),
+			big_endian: false,
+			pointer_width: Width::U32,
+			relative_to: RelativeTo::Start,
+			null_pointer: 0,
+			markers: Vec::new(),
+			bases: Vec::new(),
+			contexts: Vec::new(),
+		}
 	}
 
-	pub fn seek(&mut self, pos: SeekFrom) -> Result<()> {
-		self.inner.seek(pos)?;
-		Ok(())
+	// Attaches the current position and context path to err
+	pub fn error<E: Into<BinaryParserError>>(&self, err: E) -> BinaryParserError {
+		self.error_at(self.position, err)
 	}
 
-	pub fn read_pointer<T, F>(&mut self, func: F) -> Result<T>
+	pub fn error_at<E: Into<BinaryParserError>>(&self, pos: u64, err: E) -> BinaryParserError {
+		match err.into() {
+			err @ BinaryParserError::Context { .. } => err,
+			err => BinaryParserError::Context {
+				pos,
+				path: self.contexts.iter().map(|name| name.to_string()).collect(),
+				error: Box::new(err),
+			},
+		}
+	}
+
+	fn eof(&self, wanted: usize, available: usize) -> BinaryParserError {
+		self.error(BinaryParserError::UnexpectedEof {
+			pos: self.position,
+			wanted: wanted as u64,
+			available: available as u64,
+		})
+	}
+
+	// Names the scope for errors raised inside func, e.g. "header > objects[12] > name"
+	pub fn context<T, F>(&mut self, name: impl Into<Cow<'static, str>>, func: F) -> Result<T>
 	where
 		F: FnOnce(&mut Self) -> Result<T>,
 	{
-		let pos = self.position() + 4;
-		let offset = self.read_u32()? as u64;
-		self.seek(SeekFrom::Start(offset))?;
-		let res = func(self);
-		self.seek(SeekFrom::Start(pos))?;
+		self.contexts.push(name.into());
+		let res = func(self).map_err(|err| self.error(err));
+		self.contexts.pop();
 		res
 	}
 
-	pub fn read_pointer_offset<T, F>(&mut self, func: F, offset: u64) -> Result<T>
+	pub fn set_big_endian(&mut self, be: bool) {
+		self.big_endian = be;
+	}
+
+	pub fn is_big_endian(&self) -> bool {
+		self.big_endian
+	}
+
+	pub fn set_endian(&mut self, endian: Endian) {
+		self.big_endian = endian.is_big();
+	}
+
+	pub fn endian(&self) -> Endian {
+		if self.big_endian {
+			Endian::Big
+		} else {
+			Endian::Little
+		}
+	}
+
+	// Switches the byte order for func only. Pointers and slots written in func keep it
+	// when they are patched later, and so do the payloads of pointers
+	pub fn with_endian<T, F>(&mut self, endian: Endian, func: F) -> Result<T>
 	where
 		F: FnOnce(&mut Self) -> Result<T>,
 	{
-		let pos = self.position() + 4;
-		let offset = self.read_u32()? as u64 + offset;
-		self.seek(SeekFrom::Start(offset))?;
-		let res = func(self);
-		self.seek(SeekFrom::Start(pos))?;
-		res
+		self.with_big_endian(endian.is_big(), func)
 	}
 
-	pub fn write_pointer<F>(&mut self, func: F) -> Result<()>
+	fn with_big_endian<T, F>(&mut self, big_endian: bool, func: F) -> Result<T>
 	where
-		F: FnOnce(&mut Self) -> Result<()> + 'a,
+		F: FnOnce(&mut Self) -> Result<T>,
 	{
-		let position = self.position();
-		self.scheduled_writes.push_back(ScheduledWrite {
-			func: Box::new(func),
-			position,
-			offset: 0,
-		});
-		self.seek(SeekFrom::Current(4))?;
+		let prev = std::mem::replace(&mut self.big_endian, big_endian);
+		let res = func(self);
+		self.big_endian = prev;
+		res
+	}
 
-		Ok(())
+	pub fn set_pointer_width(&mut self, width: Width) {
+		self.pointer_width = width;
 	}
 
-	pub fn write_pointer_offset<F>(&mut self, func: F, offset: u64) -> Result<()>
+	pub fn pointer_width(&self) -> Width {
+		self.pointer_width
+	}
+
+	pub fn set_relative_to(&mut self, relative_to: RelativeTo) {
+		self.relative_to = relative_to;
+	}
+
+	pub fn relative_to(&self) -> RelativeTo {
+		self.relative_to
+	}
+
+	// Values wider than the pointer are truncated, so u64::MAX means all bits set
+	pub fn set_null_pointer(&mut self, null: u64) {
+		self.null_pointer = null;
+	}
+
+	pub fn null_pointer(&self) -> u64 {
+		self.null_pointer
+	}
+
+	fn null_pointer_value(&self, options: &PointerOptions, width: Width) -> u64 {
+		options.null.unwrap_or(self.null_pointer) & width.max_value()
+	}
+
+	pub fn push_marker(&mut self, pos: u64) {
+		self.markers.push(pos);
+	}
+
+	pub fn pop_marker(&mut self) -> Option<u64> {
+		self.markers.pop()
+	}
+
+	// Pointers relative to the start and alignment are resolved against the innermost base
+	pub fn push_base(&mut self, pos: u64) {
+		self.bases.push(pos);
+	}
+
+	pub fn pop_base(&mut self) -> Option<u64> {
+		self.bases.pop()
+	}
+
+	pub fn base(&self) -> u64 {
+		self.bases.last().copied().unwrap_or(0)
+	}
+
+	pub fn with_base<T, F>(&mut self, pos: u64, func: F) -> Result<T>
 	where
-		F: FnOnce(&mut Self) -> Result<()> + 'a,
+		F: FnOnce(&mut Self) -> Result<T>,
 	{
-		let position = self.position();
-		self.scheduled_writes.push_back(ScheduledWrite {
-			func: Box::new(func),
-			position,
-			offset,
-		});
-		self.seek(SeekFrom::Current(4))?;
+		self.push_base(pos);
+		let res = func(self);
+		self.pop_base();
+		res
+	}
 
-		Ok(())
+	// Records positions relative to the current base as holding pointers, e.g. from read_pof0
+	pub fn mark_pointers<I: IntoIterator<Item = u64>>(&mut self, positions: I) {
+		let base = self.base();
+		self.marked_pointers
+			.extend(positions.into_iter().map(|pos| base + pos));
 	}
 
-	pub fn pending_writes(&self) -> bool {
-		!self.scheduled_writes.is_empty()
+	pub fn marked_pointers(&self) -> impl Iterator<Item = u64> + '_ {
+		self.marked_pointers.iter().copied()
 	}
 
-	pub fn finish_writes(mut self) -> Result<Self> {
-		let mut new = Self {
-			big_endian: self.big_endian,
-			inner: self.inner,
-			scheduled_writes: VecDeque::new(),
-		};
-		while let Some(write) = self
-			.scheduled_writes
-			.pop_front()
-			.map_or_else(|| new.scheduled_writes.pop_front(), |v| Some(v))
-		{
-			let pos = new.position();
-			(write.func)(&mut new)?;
-			let new_pos = new.position();
-			new.seek(SeekFrom::Start(write.position))?;
-			new.write_u32((pos - write.offset) as u32)?;
-			new.seek(SeekFrom::Start(new_pos))?;
-		}
-		Ok(new)
+	// Marked pointers that no read_pointer call has followed yet
+	pub fn unvisited_pointers(&self) -> Vec<u64> {
+		self.marked_pointers()
+			.filter(|pos| !self.visited_pointers.contains(pos))
+			.collect()
 	}
 
-	pub fn align_seek(&mut self, alignment: u64) -> Result<()> {
-		let pos = self.position();
-		let offset = if pos & (alignment - 1) != 0 {
-			(pos & !(alignment - 1)) + alignment
-		} else {
-			pos
+	fn misalignment(&self, alignment: u64) -> u64 {
+		self.position.wrapping_sub(self.base()) & (alignment - 1)
+	}
+
+	// The position a pointer at field is relative to, including the options offset
+	fn pointer_origin(&self, options: &PointerOptions, field: u64) -> Result<u64> {
+		let origin = match options.relative_to.unwrap_or(self.relative_to) {
+			RelativeTo::Start => self.base(),
+			RelativeTo::Field => field,
+			RelativeTo::Marker => match self.markers.last() {
+				Some(marker) => *marker,
+				None => return Err(self.error_at(field, BinaryParserError::MissingMarker)),
+			},
 		};
-		self.seek(SeekFrom::Start(offset))
+		origin.checked_add(options.offset).ok_or_else(|| {
+			self.error_at(
+				field,
+				BinaryParserError::PointerOutOfBounds {
+					pointer: options.offset,
+					len: self.len,
+				},
+			)
+		})
 	}
 
-	pub fn align_write(&mut self, alignment: u64) -> Result<()> {
-		while self.position() & (alignment - 1) != 0 {
-			self.write_u8(0)?;
-		}
-		Ok(())
+	pub fn position(&self) -> u64 {
+		self.position
 	}
 
-	pub fn align_write_value(&mut self, alignment: u64, value: u8) -> Result<()> {
-		while self.position() & (alignment - 1) != 0 {
-			self.write_u8(value)?;
-		}
-		Ok(())
+	pub fn len(&self) -> u64 {
+		self.len
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len == 0
+	}
+
+	pub fn pending_writes(&self) -> bool {
+		self.queued_writes() != 0
+	}
+
+	fn queued_writes(&self) -> usize {
+		let sections = self.sections.iter().map(|section| section.writes.len());
+		sections.sum::<usize>() + self.string_writes.len()
+	}
+
+	pub fn get_ref(&self) -> &S {
+		&self.inner
+	}
+
+	// Pending writes are discarded, call finish_writes first when writing
+	pub fn into_inner(self) -> S {
+		self.inner
+	}
+}
+
+impl<'a, S: Seek> BinaryParser<'a, S> {
+	pub fn from_stream(mut inner: S) -> Result<Self> {
+		let position = inner.stream_position()?;
+		let len = inner.seek(SeekFrom::End(0))?;
+		inner.seek(SeekFrom::Start(position))?;
+		Ok(Self::from_inner(inner, position, len))
+	}
+
+	pub fn seek(&mut self, pos: SeekFrom) -> Result<()> {
+		let target = match pos {
+			SeekFrom::Start(pos) => Some(pos),
+			SeekFrom::Current(delta) => self.position.checked_add_signed(delta),
+			SeekFrom::End(delta) => self.len.checked_add_signed(delta),
+		};
+		let Some(target) = target.filter(|target| *target >= self.start) else {
+			let start = self.start;
+			return Err(self.error(BinaryParserError::SeekBeforeStart { start }));
+		};
+		self.position = self
+			.inner
+			.seek(SeekFrom::Start(target))
+			.map_err(|err| self.error(err))?;
+		Ok(())
+	}
+
+	// Runs func on a view of start..start + len sharing this stream. Positions stay absolute
+	// and reading past the end of the view is an EOF error, push_base(start) on the view
+	// resolves pointers from its start instead
+	pub fn sub_view<T, F>(&mut self, start: u64, len: u64, func: F) -> Result<T>
+	where
+		F: FnOnce(&mut BinaryParser<'a, &mut S>) -> Result<T>,
+	{
+		let end = start.checked_add(len);
+		let end = end.filter(|end| start >= self.start && *end <= self.len);
+		let Some(end) = end else {
+			return Err(self.error_at(
+				start,
+				BinaryParserError::UnexpectedEof {
+					pos: start,
+					wanted: len,
+					available: self.len.saturating_sub(start),
+				},
+			));
+		};
+		let pos = self.position();
+		self.seek(SeekFrom::Start(start))?;
+		let mut view = BinaryParser::from_inner(&mut self.inner, start, end);
+		view.start = start;
+		view.big_endian = self.big_endian;
+		view.pointer_width = self.pointer_width;
+		view.relative_to = self.relative_to;
+		view.null_pointer = self.null_pointer;
+		view.markers = self.markers.clone();
+		view.bases = self.bases.clone();
+		view.contexts = self.contexts.clone();
+		view.marked_pointers = self.marked_pointers.clone();
+		let res = func(&mut view);
+		// Deferred writes, slots and chunks can't outlive the view, so leaving any is an error
+		let (writes, slots, chunks) = (view.queued_writes(), view.slots.len(), view.chunks.len());
+		let res = match res {
+			Ok(_) if writes + slots + chunks != 0 => {
+				Err(view.error(BinaryParserError::PendingInView {
+					writes,
+					slots,
+					chunks,
+				}))
+			}
+			res => res,
+		};
+		let len = view.len;
+		let marked = std::mem::take(&mut view.marked_pointers);
+		let visited = std::mem::take(&mut view.visited_pointers);
+		drop(view);
+		self.len = self.len.max(len);
+		self.marked_pointers.extend(marked);
+		self.visited_pointers.extend(visited);
+		self.seek(SeekFrom::Start(pos))?;
+		res
+	}
+
+	pub fn align_seek(&mut self, alignment: u64) -> Result<()> {
+		let pos = self.position();
+		let offset = match self.misalignment(alignment) {
+			0 => pos,
+			misalignment => pos + alignment - misalignment,
+		};
+		self.seek(SeekFrom::Start(offset))
+	}
+}
+
+impl<'a, S: Read + Seek> BinaryParser<'a, S> {
+	fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+		let available = self.len.saturating_sub(self.position) as usize;
+		if buf.len() > available {
+			return Err(self.eof(buf.len(), available));
+		}
+		if let Err(err) = self.inner.read_exact(buf) {
+			let err = self.error(err);
+			self.position = self.inner.stream_position()?;
+			return Err(err);
+		}
+		self.position += buf.len() as u64;
+		Ok(())
+	}
+
+	int_impl!(read, u8, 1);
+	int_impl!(read, u16, 2);
+	int_impl!(read, u32, 4);
+	int_impl!(read, u64, 8);
+	int_impl!(read, i8, 1);
+	int_impl!(read, i16, 2);
+	int_impl!(read, i32, 4);
+	int_impl!(read, i64, 8);
+	int_impl!(read, f32, 4);
+	int_impl!(read, f64, 8);
+
+	pub fn read_null_string(&mut self) -> Result<String> {
+		let pos = self.position();
+		let mut buf = vec![];
+		while self.position < self.len {
+			let byte = self.read_u8()?;
+			if byte == 0x00 {
+				break;
+			}
+			buf.push(byte);
+		}
+		let string = std::str::from_utf8(&buf).map_err(|err| self.error_at(pos, err))?;
+		Ok(String::from(string))
+	}
+
+	pub fn read_string(&mut self, length: usize) -> Result<String> {
+		let pos = self.position();
+		let mut buf = vec![0; length];
+		self.read_exact(&mut buf)?;
+		let string = std::str::from_utf8(&buf).map_err(|err| self.error_at(pos, err))?;
+		Ok(String::from(string))
+	}
+
+	pub fn read_null_string_pointer(&mut self) -> Result<String> {
+		self.read_pointer(|reader| reader.read_null_string())
+	}
+
+	pub fn read_buf(&mut self, length: usize) -> Result<Vec<u8>> {
+		let mut buf = vec![0; length];
+		self.read_exact(&mut buf)?;
+		Ok(buf)
+	}
+
+	pub fn expect_magic(&mut self, expected: &[u8]) -> Result<()> {
+		let pos = self.position();
+		let found = self.read_buf(expected.len())?;
+		if found != expected {
+			return Err(self.error_at(
+				pos,
+				BinaryParserError::BadMagic {
+					expected: expected.to_vec(),
+					found,
+					pos,
+				},
+			));
+		}
+		Ok(())
+	}
+
+	pub fn read<T: BinRead>(&mut self) -> Result<T>
+	where
+		T::Args: Default,
+	{
+		T::read(self)
+	}
+
+	pub fn read_args<T: BinRead>(&mut self, args: T::Args) -> Result<T> {
+		T::read_args(self, args)
+	}
+
+	pub fn read_parser(&mut self, length: usize) -> Result<BinaryParser<'a>> {
+		let buf = self.read_buf(length)?;
+		Ok(BinaryParser::from_buf(buf))
+	}
+
+	// Sets the byte order in which the u32 at the current position reads as expected
+	pub fn detect_endian(&mut self, expected: u32) -> Result<Endian> {
+		self.detect_endian_by(|parser| Ok(parser.read_u32()? == expected))
+	}
+
+	// Runs func in the current byte order, then the other, and keeps the first one it accepts.
+	// An error counts as not accepted, it is only returned if neither order is accepted.
+	// The position is restored afterwards
+	pub fn detect_endian_by<F>(&mut self, mut func: F) -> Result<Endian>
+	where
+		F: FnMut(&mut Self) -> Result<bool>,
+	{
+		let pos = self.position();
+		let current = self.endian();
+		let other = match current {
+			Endian::Big => Endian::Little,
+			_ => Endian::Big,
+		};
+		let mut error = None;
+		for endian in [current, other] {
+			let res = self.with_endian(endian, &mut func);
+			self.seek(SeekFrom::Start(pos))?;
+			match res {
+				Ok(true) => {
+					self.set_endian(endian);
+					return Ok(endian);
+				}
+				Ok(false) => {}
+				Err(err) => {
+					error.get_or_insert(err);
+				}
+			}
+		}
+		Err(error.unwrap_or_else(|| self.error(BinaryParserError::UnknownEndian)))
+	}
+
+	// Reads chunks from the current position to the end, stopping after the first error
+	pub fn chunks(&mut self, layout: ChunkLayout) -> Chunks<'_, 'a, S> {
+		Chunks {
+			parser: self,
+			layout,
+			done: false,
+		}
+	}
+
+	fn seek_pointer(&mut self, field: u64, pointer: u64) -> Result<()> {
+		if pointer < self.start || pointer > self.len {
+			return Err(self.error_at(
+				field,
+				BinaryParserError::PointerOutOfBounds {
+					pointer,
+					len: self.len,
+				},
+			));
+		}
+		self.seek(SeekFrom::Start(pointer))
+	}
+
+	pub fn read_pointer_value(&mut self, width: Width) -> Result<u64> {
+		match width {
+			Width::U16 => Ok(self.read_u16()? as u64),
+			Width::U32 => Ok(self.read_u32()? as u64),
+			Width::U64 => self.read_u64(),
+		}
+	}
+
+	// Decodes a POF0 table written by write_pof0 into pointer positions
+	pub fn read_pof0(&mut self) -> Result<Vec<u64>> {
+		let start = self.position();
+		// The length counts itself, so anything shorter would seek back into the table
+		let len = self.read_u32()?;
+		if len < 4 {
+			return Err(self.error_at(start, BinaryParserError::invalid_value("POF0 length", len)));
+		}
+		let end = start + len as u64;
+		let mut positions = vec![];
+		let mut pos = 0;
+		while self.position() < end {
+			let byte = self.read_u8()?;
+			let diff = match byte >> 6 {
+				// Padding
+				0 => break,
+				1 => (byte & 0x3F) as u64,
+				2 => u16::from_be_bytes([byte & 0x3F, self.read_u8()?]) as u64,
+				_ => {
+					let mut rest = [0; 3];
+					self.read_exact(&mut rest)?;
+					u32::from_be_bytes([byte & 0x3F, rest[0], rest[1], rest[2]]) as u64
+				}
+			};
+			pos += diff << 2;
+			positions.push(pos);
+		}
+		self.seek(SeekFrom::Start(end))?;
+		Ok(positions)
+	}
+
+	pub fn read_pointer<T, F>(&mut self, func: F) -> Result<T>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		self.read_pointer_with(PointerOptions::new(), func)
+	}
+
+	pub fn read_pointer_offset<T, F>(&mut self, func: F, offset: u64) -> Result<T>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		self.read_pointer_with(PointerOptions::new().offset(offset), func)
+	}
+
+	pub fn read_pointer_with<T, F>(&mut self, options: PointerOptions, func: F) -> Result<T>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		let field = self.position();
+		let width = options.width.unwrap_or(self.pointer_width);
+		let value = self.read_pointer_value(width)?;
+		self.visited_pointers.insert(field);
+		self.follow_pointer(&options, field, value, func)
+	}
+
+	pub fn read_optional_pointer<T, F>(&mut self, func: F) -> Result<Option<T>>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		self.read_optional_pointer_with(PointerOptions::new(), func)
+	}
+
+	pub fn read_optional_pointer_with<T, F>(
+		&mut self,
+		options: PointerOptions,
+		func: F,
+	) -> Result<Option<T>>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		let field = self.position();
+		let width = options.width.unwrap_or(self.pointer_width);
+		let value = self.read_pointer_value(width)?;
+		self.visited_pointers.insert(field);
+		if value == self.null_pointer_value(&options, width) {
+			return Ok(None);
+		}
+		self.follow_pointer(&options, field, value, func).map(Some)
+	}
+
+	fn follow_pointer<T, F>(
+		&mut self,
+		options: &PointerOptions,
+		field: u64,
+		value: u64,
+		func: F,
+	) -> Result<T>
+	where
+		F: FnOnce(&mut Self) -> Result<T>,
+	{
+		let origin = self.pointer_origin(options, field)?;
+		let offset = value.checked_add(origin).ok_or_else(|| {
+			self.error_at(
+				field,
+				BinaryParserError::PointerOutOfBounds {
+					pointer: value,
+					len: self.len,
+				},
+			)
+		})?;
+		let pos = self.position();
+		self.seek_pointer(field, offset)?;
+		let res = func(self);
+		self.seek(SeekFrom::Start(pos))?;
+		res
+	}
+}
+
+impl<'a, S: Write + Seek> BinaryParser<'a, S> {
+	fn write_all(&mut self, buf: &[u8]) -> Result<()> {
+		if let Err(err) = self.inner.write_all(buf) {
+			let err = self.error(err);
+			self.position = self.inner.stream_position()?;
+			return Err(err);
+		}
+		self.position += buf.len() as u64;
+		self.len = self.len.max(self.position);
+		if let Some(capture) = &mut self.capture {
+			capture.extend_from_slice(buf);
+		}
+		Ok(())
+	}
+
+	int_impl!(write, u8, 1);
+	int_impl!(write, u16, 2);
+	int_impl!(write, u32, 4);
+	int_impl!(write, u64, 8);
+	int_impl!(write, i8, 1);
+	int_impl!(write, i16, 2);
+	int_impl!(write, i32, 4);
+	int_impl!(write, i64, 8);
+	int_impl!(write, f32, 4);
+	int_impl!(write, f64, 8);
+
+	pub fn write_string(&mut self, data: &str) -> Result<()> {
+		let buf = data.as_bytes();
+		self.write_all(buf)?;
+		Ok(())
+	}
+
+	pub fn write_null_string(&mut self, data: &str) -> Result<()> {
+		let buf = data.as_bytes();
+		self.write_all(buf)?;
+		self.write_u8(0)?;
+		Ok(())
+	}
+
+	pub fn set_string_pool(&mut self, pool: StringPool) {
+		self.string_pool = pool;
+	}
+
+	pub fn string_pool(&self) -> StringPool {
+		self.string_pool
+	}
+
+	pub fn set_write_order(&mut self, order: WriteOrder) {
+		self.write_order = order;
+	}
+
+	pub fn write_order(&self) -> WriteOrder {
+		self.write_order
+	}
+
+	// Emits a relocation table after all deferred writes in finish_writes
+	pub fn set_relocation_table(&mut self, table: RelocationTable) {
+		self.set_relocation_table_with(move |writer, positions| match table {
+			RelocationTable::Pof0 => writer.write_pof0(positions),
+			RelocationTable::U32List => positions
+				.iter()
+				.try_for_each(|pos| writer.write_value(Width::U32, *pos)),
+		});
+	}
+
+	pub fn set_relocation_table_with<F>(&mut self, func: F)
+	where
+		F: FnOnce(&mut Self, &[u64]) -> Result<()> + 'a,
+	{
+		self.relocation_table = Some(Box::new(func));
+	}
+
+	// Sorted pointer positions patched by finish_writes
+	pub fn relocations(&self) -> &[u64] {
+		&self.relocations
+	}
+
+	pub fn write_pof0(&mut self, positions: &[u64]) -> Result<()> {
+		let mut buf = vec![];
+		let mut last = 0;
+		for &pos in positions {
+			let diff = pos.wrapping_sub(last);
+			if pos < last || diff % 4 != 0 || diff >> 2 > 0x3FFFFFFF {
+				return Err(self.error(BinaryParserError::invalid_value("POF0 position", pos)));
+			}
+			let diff = diff as u32 >> 2;
+			match diff {
+				0..=0x3F => buf.push(0x40 | diff as u8),
+				0x40..=0x3FFF => buf.extend_from_slice(&(0x8000 | diff as u16).to_be_bytes()),
+				_ => buf.extend_from_slice(&(0xC0000000 | diff).to_be_bytes()),
+			}
+			last = pos;
+		}
+		let len = (buf.len() + 4).next_multiple_of(4);
+		buf.resize(len - 4, 0);
+		self.write_value(Width::U32, len as u64)?;
+		self.write_all(&buf)
+	}
+
+	// Like write_pointer_value, but for sizes and positions that aren't pointers
+	fn write_value(&mut self, width: Width, value: u64) -> Result<()> {
+		if value > width.max_value() {
+			return Err(self.error(BinaryParserError::ValueOverflow { value, width }));
+		}
+		self.write_pointer_value(width, value)
+	}
+
+	// Registers a section for deferred writes, or updates its alignment.
+	// Sections are laid out by finish_writes in the order they were first defined,
+	// after the unnamed default section "" and before trailing pooled strings
+	pub fn define_section<N: Into<Cow<'static, str>>>(&mut self, name: N, alignment: u64) {
+		let index = self.section_index(name.into());
+		self.sections[index].alignment = alignment;
+	}
+
+	// Moves the listed sections to the front in the given order, others keep their relative order
+	pub fn set_section_order<I, N>(&mut self, order: I)
+	where
+		I: IntoIterator<Item = N>,
+		N: Into<Cow<'static, str>>,
+	{
+		for (i, name) in order.into_iter().enumerate() {
+			let index = self.section_index(name.into());
+			let section = self.sections.remove(index);
+			self.sections.insert(i.min(self.sections.len()), section);
+		}
+	}
+
+	fn section_index(&mut self, name: Cow<'static, str>) -> usize {
+		match self
+			.sections
+			.iter()
+			.position(|section| section.name == name)
+		{
+			Some(index) => index,
+			None => {
+				self.sections.push(Section::new(name, 1));
+				self.sections.len() - 1
+			}
+		}
+	}
+
+	pub fn write_null_string_pointer(&mut self, data: &str) -> Result<()> {
+		self.write_null_string_pointer_with(PointerOptions::new(), data)
+	}
+
+	pub fn write_null_string_pointer_with(
+		&mut self,
+		options: PointerOptions,
+		data: &str,
+	) -> Result<()> {
+		let mut buf = data.as_bytes().to_vec();
+		buf.push(0);
+		let mut options = options;
+		if self.string_pool != StringPool::Disabled {
+			options.dedup = Some(Dedup::Output(Some(buf.clone())));
+		}
+		let string = buf.clone();
+		let func = Box::new(move |writer: &mut Self| writer.write_all(&buf));
+		self.schedule_write(options, func, Some(string))
+	}
+
+	pub fn write_buf(&mut self, data: &[u8]) -> Result<()> {
+		self.write_all(data)?;
+		Ok(())
+	}
+
+	pub fn write<T: BinWrite + ?Sized>(&mut self, value: &T) -> Result<()>
+	where
+		T::Args: Default,
+	{
+		value.write(self)
+	}
+
+	pub fn write_args<T: BinWrite + ?Sized>(&mut self, value: &T, args: T::Args) -> Result<()> {
+		value.write_args(self, args)
+	}
+
+	pub fn write_parser(&mut self, parser: BinaryParser) -> Result<()> {
+		let new = parser.to_buf()?;
+		self.write_buf(&new)
+	}
+
+	pub fn write_pointer_value(&mut self, width: Width, pointer: u64) -> Result<()> {
+		if pointer > width.max_value() {
+			return Err(self.error(BinaryParserError::PointerOverflow { pointer, width }));
+		}
+		match width {
+			Width::U16 => self.write_u16(pointer as u16),
+			Width::U32 => self.write_u32(pointer as u32),
+			Width::U64 => self.write_u64(pointer),
+		}
+	}
+
+	pub fn reserve_u16(&mut self) -> Result<Slot> {
+		self.reserve(Width::U16)
+	}
+
+	pub fn reserve_u32(&mut self) -> Result<Slot> {
+		self.reserve(Width::U32)
+	}
+
+	pub fn reserve_u64(&mut self) -> Result<Slot> {
+		self.reserve(Width::U64)
+	}
+
+	// Writes a zeroed placeholder, finish_writes fails if it is never filled
+	pub fn reserve(&mut self, width: Width) -> Result<Slot> {
+		let slot = Slot {
+			position: self.position(),
+			width,
+			big_endian: self.big_endian,
+		};
+		self.write_pointer_value(width, 0)?;
+		self.slots.insert(slot.position, width);
+		Ok(slot)
+	}
+
+	pub fn fill(&mut self, slot: Slot, value: u64) -> Result<()> {
+		if value > slot.width.max_value() {
+			return Err(self.error_at(
+				slot.position,
+				BinaryParserError::ValueOverflow {
+					value,
+					width: slot.width,
+				},
+			));
+		}
+		let pos = self.position();
+		self.seek(SeekFrom::Start(slot.position))?;
+		self.with_big_endian(slot.big_endian, |writer| {
+			writer.write_pointer_value(slot.width, value)
+		})?;
+		self.seek(SeekFrom::Start(pos))?;
+		self.slots.remove(&slot.position);
+		Ok(())
+	}
+
+	// Fills the slot with the number of bytes between its end and the current position
+	pub fn fill_with_length_since(&mut self, slot: Slot) -> Result<()> {
+		let length = self
+			.position()
+			.saturating_sub(slot.position + slot.width.bytes());
+		self.fill(slot, length)
+	}
+
+	// Writes the magic and reserves the size, which end_chunk fills in. Chunks can be nested
+	pub fn begin_chunk(&mut self, magic: &[u8], size_width: Width) -> Result<()> {
+		self.begin_chunk_with(magic, ChunkLayout::new(magic.len(), size_width))
+	}
+
+	pub fn begin_chunk_with(&mut self, magic: &[u8], layout: ChunkLayout) -> Result<()> {
+		if magic.len() != layout.magic_len {
+			return Err(self.error(BinaryParserError::invalid_value(
+				"chunk magic",
+				format!("{magic:02x?}"),
+			)));
+		}
+		let start = self.position();
+		self.write_all(magic)?;
+		let size = self.reserve(layout.size_width)?;
+		self.chunks.push(OpenChunk {
+			start,
+			size,
+			layout,
+		});
+		Ok(())
+	}
+
+	pub fn end_chunk(&mut self) -> Result<()> {
+		let chunk = self
+			.chunks
+			.pop()
+			.ok_or_else(|| self.error(BinaryParserError::MissingChunk))?;
+		self.align_write(chunk.layout.alignment)?;
+		let size = chunk.size;
+		if chunk.layout.size_includes_header {
+			let pos = self.position();
+			let len = pos.checked_sub(chunk.start).ok_or_else(|| {
+				self.error_at(
+					chunk.start,
+					BinaryParserError::invalid_value("chunk end", pos),
+				)
+			})?;
+			self.fill(size, len)
+		} else {
+			self.fill_with_length_since(size)
+		}
+	}
+
+	pub fn write_pointer<F>(&mut self, func: F) -> Result<()>
+	where
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new(), func)
+	}
+
+	pub fn write_pointer_offset<F>(&mut self, func: F, offset: u64) -> Result<()>
+	where
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new().offset(offset), func)
+	}
+
+	pub fn write_pointer_with<F>(&mut self, options: PointerOptions, func: F) -> Result<()>
+	where
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.schedule_write(options, Box::new(func), None)
+	}
+
+	pub fn write_pointer_aligned<F>(&mut self, alignment: u64, func: F) -> Result<()>
+	where
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new().align(alignment, 0), func)
+	}
+
+	pub fn write_pointer_in<N, F>(&mut self, section: N, func: F) -> Result<()>
+	where
+		N: Into<Cow<'static, str>>,
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new().section(section), func)
+	}
+
+	pub fn write_pointer_dedup<F>(&mut self, func: F) -> Result<()>
+	where
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new().dedup(), func)
+	}
+
+	pub fn write_pointer_dedup_key<K, F>(&mut self, key: K, func: F) -> Result<()>
+	where
+		K: AsRef<[u8]>,
+		F: FnOnce(&mut Self) -> Result<()> + 'a,
+	{
+		self.write_pointer_with(PointerOptions::new().dedup_key(key), func)
+	}
+
+	fn schedule_write(
+		&mut self,
+		options: PointerOptions,
+		func: WriteFn<'a, S>,
+		string: Option<Vec<u8>>,
+	) -> Result<()> {
+		let trailing = string.is_some() && self.string_pool == StringPool::Trailing;
+		let position = self.position();
+		let width = options.width.unwrap_or(self.pointer_width);
+		let origin = self.pointer_origin(&options, position)?;
+		let unbased = self.bases.is_empty();
+		let relative_to = options.relative_to.unwrap_or(self.relative_to);
+		let write = ScheduledWrite {
+			func,
+			field: PointerField {
+				position,
+				origin,
+				width,
+				from_start: unbased && relative_to == RelativeTo::Start,
+				big_endian: self.big_endian,
+			},
+			base: self.base(),
+			unbased,
+			dedup: options.dedup,
+			priority: options.priority,
+			depth: self.depth,
+			align: options.align,
+			string,
+			big_endian: self.big_endian,
+		};
+		let depth_first = self.write_order == WriteOrder::DepthFirst;
+		if trailing {
+			enqueue(&mut self.string_writes, write, depth_first);
+		} else {
+			let index = self.section_index(options.section.unwrap_or_default());
+			enqueue(&mut self.sections[index].writes, write, depth_first);
+		}
+		// Zeroed until finish_writes patches it, so len covers the field
+		self.write_pointer_value(width, 0)
+	}
+
+	pub fn write_null_pointer(&mut self) -> Result<()> {
+		self.write_null_pointer_with(PointerOptions::new())
+	}
+
+	pub fn write_null_pointer_with(&mut self, options: PointerOptions) -> Result<()> {
+		let width = options.width.unwrap_or(self.pointer_width);
+		let null = self.null_pointer_value(&options, width);
+		self.write_pointer_value(width, null)
+	}
+
+	// Writes the null value for None, otherwise schedules func with the value like write_pointer
+	pub fn write_optional_pointer<T, F>(&mut self, value: Option<T>, func: F) -> Result<()>
+	where
+		T: 'a,
+		F: FnOnce(&mut Self, T) -> Result<()> + 'a,
+	{
+		self.write_optional_pointer_with(PointerOptions::new(), value, func)
+	}
+
+	pub fn write_optional_pointer_with<T, F>(
+		&mut self,
+		options: PointerOptions,
+		value: Option<T>,
+		func: F,
+	) -> Result<()>
+	where
+		T: 'a,
+		F: FnOnce(&mut Self, T) -> Result<()> + 'a,
+	{
+		match value {
+			Some(value) => self.write_pointer_with(options, move |writer| func(writer, value)),
+			None => self.write_null_pointer_with(options),
+		}
+	}
+
+	pub fn finish_writes(mut self) -> Result<Self> {
+		let mut targets = HashMap::new();
+		let mut truncated = false;
+		let mut current = None;
+		while let Some((index, write)) = self.next_write() {
+			let key = write.dedup.map(|dedup| (write.base, dedup));
+			// An earlier copy is only reused if it satisfies this write's alignment
+			let (base, align) = (write.base, write.align);
+			let reusable = |target: &&u64| match align {
+				Some((alignment, _)) => target.wrapping_sub(base) & (alignment - 1) == 0,
+				None => true,
+			};
+			let reused = key.as_ref().and_then(|key| targets.get(key));
+			if let Some(target) = reused.filter(reusable) {
+				self.patch_pointer(write.field, write.base, *target)?;
+				continue;
+			}
+
+			// Writes scheduled into an earlier section after it was drained are appended
+			// at the current end, starting that section again
+			if current != Some(index) {
+				current = Some(index);
+				if let Some(section) = self.sections.get(index) {
+					self.align_write(section.alignment)?;
+				}
+			}
+
+			let start = self.position();
+			if let Some((alignment, pad)) = write.align {
+				self.with_base(write.base, |writer| {
+					writer.align_write_value(alignment, pad)
+				})?;
+			}
+			let pos = self.position();
+			let queued = self.queued_writes();
+			if let Some((_, Dedup::Output(None))) = key {
+				self.capture = Some(vec![]);
+			}
+			let bases = std::mem::replace(&mut self.bases, vec![write.base]);
+			let big_endian = std::mem::replace(&mut self.big_endian, write.big_endian);
+			self.depth = write.depth + 1;
+			let res = (write.func)(&mut self);
+			self.depth = 0;
+			self.big_endian = big_endian;
+			self.bases = bases;
+			let capture = self.capture.take();
+			res?;
+
+			let key = match (key, capture) {
+				(Some((base, Dedup::Output(None))), Some(capture)) => {
+					let nested = self.queued_writes() > queued;
+					let contiguous = pos + capture.len() as u64 == self.position();
+					(!nested && contiguous).then_some((base, Dedup::Output(Some(capture))))
+				}
+				(key, _) => key,
+			};
+			// A duplicate at the end can only be dropped if the stream can be cut back to len
+			let at_end = self.position() == self.len;
+			let droppable = !at_end || self.truncate.is_some();
+			let target = match key
+				.as_ref()
+				.and_then(|key| targets.get(key))
+				.filter(reusable)
+			{
+				Some(target) if droppable => {
+					if at_end {
+						self.len = start;
+						truncated = true;
+					}
+					self.seek(SeekFrom::Start(start))?;
+					*target
+				}
+				_ => {
+					if let Some(key) = key {
+						targets.insert(key, pos);
+					}
+					pos
+				}
+			};
+			self.patch_pointer(write.field, write.base, target)?;
+		}
+		if let Some((&pos, &width)) = self.slots.iter().next() {
+			return Err(self.error_at(pos, BinaryParserError::UnfilledSlot { width }));
+		}
+		if let (true, Some(truncate)) = (truncated, self.truncate) {
+			truncate(&mut self.inner, self.len).map_err(|err| self.error(err))?;
+		}
+		self.relocations.sort_unstable();
+		if let Some(func) = self.relocation_table.take() {
+			self.seek(SeekFrom::Start(self.len))?;
+			let relocations = std::mem::take(&mut self.relocations);
+			let res = func(&mut self, &relocations);
+			self.relocations = relocations;
+			res?;
+		}
+		self.inner.flush().map_err(|err| self.error(err))?;
+		Ok(self)
+	}
+
+	// Takes from the first section with pending writes, with trailing strings last
+	fn next_write(&mut self) -> Option<(usize, ScheduledWrite<'a, S>)> {
+		self.sections
+			.iter_mut()
+			.enumerate()
+			.find_map(|(index, section)| Some((index, section.writes.pop_front()?)))
+			.or_else(|| Some((self.sections.len(), self.string_writes.pop_front()?)))
+	}
+
+	fn patch_pointer(&mut self, field: PointerField, base: u64, target: u64) -> Result<()> {
+		self.relocations.push(field.position.saturating_sub(base));
+		let pos = self.position();
+		self.seek(SeekFrom::Start(field.position))?;
+		let pointer = target.checked_sub(field.origin).ok_or_else(|| {
+			self.error(BinaryParserError::NegativePointer {
+				target,
+				origin: field.origin,
+			})
+		})?;
+		self.with_big_endian(field.big_endian, |writer| {
+			writer.write_pointer_value(field.width, pointer)
+		})?;
+		self.seek(SeekFrom::Start(pos))
+	}
+
+	pub fn align_write(&mut self, alignment: u64) -> Result<()> {
+		self.align_write_value(alignment, 0)
+	}
+
+	pub fn align_write_value(&mut self, alignment: u64, value: u8) -> Result<()> {
+		while self.misalignment(alignment) != 0 {
+			self.write_u8(value)?;
+		}
+		Ok(())
+	}
+}
+
+impl<'a, S: Read + Write + Seek> BinaryParser<'a, S> {
+	// Adds delta to every non null marked pointer, for moving a blob to a new offset
+	pub fn relocate(&mut self, delta: i64) -> Result<()> {
+		let pos = self.position();
+		let width = self.pointer_width;
+		let null = self.null_pointer_value(&PointerOptions::new(), width);
+		let pointers = self.marked_pointers.iter().copied().collect::<Vec<_>>();
+		for field in pointers {
+			self.seek(SeekFrom::Start(field))?;
+			let value = self.read_pointer_value(width)?;
+			if value == null {
+				continue;
+			}
+			let pointer = value
+				.checked_add_signed(delta)
+				.filter(|pointer| *pointer <= width.max_value())
+				.ok_or_else(|| {
+					self.error_at(
+						field,
+						BinaryParserError::PointerOverflow {
+							pointer: value.wrapping_add_signed(delta),
+							width,
+						},
+					)
+				})?;
+			self.seek(SeekFrom::Start(field))?;
+			self.write_pointer_value(width, pointer)?;
+		}
+		self.seek(SeekFrom::Start(pos))
+	}
+}
+
+macro_rules! bin_impl {
+	($($ty: ty),*) => {
+		paste::item! {
+			$(
+				impl BinRead for $ty {
+					type Args = ();
+
+					fn read_args<S: Read + Seek>(parser: &mut BinaryParser<S>, _: ()) -> Result<Self> {
+						parser.[< read_ $ty >]()
+					}
+				}
+
+				impl BinWrite for $ty {
+					type Args = ();
+
+					fn write_args<S: Write + Seek>(
+						&self,
+						parser: &mut BinaryParser<S>,
+						_: (),
+					) -> Result<()> {
+						parser.[< write_ $ty >](*self)
+					}
+				}
+			)*
+		}
+	};
+}
+
+bin_impl!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl BinRead for String {
+	type Args = StringArgs;
+
+	fn read_args<S: Read + Seek>(parser: &mut BinaryParser<S>, args: StringArgs) -> Result<Self> {
+		match args {
+			StringArgs::NullTerminated => parser.read_null_string(),
+			StringArgs::Fixed(length) => {
+				let pos = parser.position();
+				let buf = parser.read_buf(length)?;
+				let end = buf.iter().position(|byte| *byte == 0).unwrap_or(length);
+				let str =
+					std::str::from_utf8(&buf[..end]).map_err(|err| parser.error_at(pos, err))?;
+				Ok(str.to_string())
+			}
+			StringArgs::Pointer => parser.read_null_string_pointer(),
+		}
+	}
+}
+
+impl BinWrite for str {
+	type Args = StringArgs;
+
+	fn write_args<S: Write + Seek>(
+		&self,
+		parser: &mut BinaryParser<S>,
+		args: StringArgs,
+	) -> Result<()> {
+		match args {
+			StringArgs::NullTerminated => parser.write_null_string(self),
+			StringArgs::Fixed(length) => {
+				if self.len() > length {
+					return Err(parser.error(BinaryParserError::invalid_value(
+						format!("string of {length} bytes"),
+						self,
+					)));
+				}
+				parser.write_string(self)?;
+				parser.write_buf(&vec![0; length - self.len()])
+			}
+			StringArgs::Pointer => parser.write_null_string_pointer(self),
+		}
+	}
+}
+
+impl BinWrite for String {
+	type Args = StringArgs;
+
+	fn write_args<S: Write + Seek>(
+		&self,
+		parser: &mut BinaryParser<S>,
+		args: StringArgs,
+	) -> Result<()> {
+		self.as_str().write_args(parser, args)
+	}
+}
+
+impl<T: BinRead> BinRead for Vec<T>
+where
+	T::Args: Default,
+{
+	type Args = Count;
+
+	fn read_args<S: Read + Seek>(
+		parser: &mut BinaryParser<S>,
+		Count(count): Count,
+	) -> Result<Self> {
+		// Bounded by the remaining length so a corrupt count fails with EOF instead of allocating
+		let remaining = parser.len().saturating_sub(parser.position());
+		let mut items = Vec::with_capacity(count.min(remaining as usize));
+		for _ in 0..count {
+			items.push(T::read(parser)?);
+		}
+		Ok(items)
+	}
+}
+
+// Writes only the elements, the count is up to the format
+impl<T: BinWrite> BinWrite for [T]
+where
+	T::Args: Default,
+{
+	type Args = ();
+
+	fn write_args<S: Write + Seek>(&self, parser: &mut BinaryParser<S>, _: ()) -> Result<()> {
+		for item in self {
+			item.write(parser)?;
+		}
+		Ok(())
+	}
+}
+
+impl<T: BinWrite> BinWrite for Vec<T>
+where
+	T::Args: Default,
+{
+	type Args = ();
+
+	fn write_args<S: Write + Seek>(&self, parser: &mut BinaryParser<S>, _: ()) -> Result<()> {
+		self.as_slice().write(parser)
+	}
+}
+
+impl<T: BinRead, const N: usize> BinRead for [T; N]
+where
+	T::Args: Clone,
+{
+	type Args = T::Args;
+
+	fn read_args<S: Read + Seek>(parser: &mut BinaryParser<S>, args: T::Args) -> Result<Self> {
+		let mut items = Vec::with_capacity(N);
+		for _ in 0..N {
+			items.push(T::read_args(parser, args.clone())?);
+		}
+		Ok(items.try_into().unwrap_or_else(|_| unreachable!()))
+	}
+}
+
+impl<T: BinWrite, const N: usize> BinWrite for [T; N]
+where
+	T::Args: Clone,
+{
+	type Args = T::Args;
+
+	fn write_args<S: Write + Seek>(
+		&self,
+		parser: &mut BinaryParser<S>,
+		args: T::Args,
+	) -> Result<()> {
+		for item in self {
+			item.write_args(parser, args.clone())?;
+		}
+		Ok(())
+	}
+}
+
+// None at the end of the stream, for trailing fields that older versions of a format lack
+impl<T: BinRead> BinRead for Option<T> {
+	type Args = OptionArgs<T::Args>;
+
+	fn read_args<S: Read + Seek>(
+		parser: &mut BinaryParser<S>,
+		args: OptionArgs<T::Args>,
+	) -> Result<Self> {
+		match args {
+			OptionArgs::Trailing(args) => {
+				if parser.position() >= parser.len() {
+					return Ok(None);
+				}
+				T::read_args(parser, args).map(Some)
+			}
+			OptionArgs::Pointer(args) => {
+				parser.read_optional_pointer(|reader| T::read_args(reader, args))
+			}
+		}
+	}
+}
+
+impl<T> BinWrite for Option<T>
+where
+	T: BinWrite + Clone + 'static,
+	T::Args: 'static,
+{
+	type Args = OptionArgs<T::Args>;
+
+	fn write_args<S: Write + Seek>(
+		&self,
+		parser: &mut BinaryParser<S>,
+		args: OptionArgs<T::Args>,
+	) -> Result<()> {
+		match args {
+			OptionArgs::Trailing(args) => match self {
+				Some(value) => value.write_args(parser, args),
+				None => Ok(()),
+			},
+			OptionArgs::Pointer(args) => parser
+				.write_optional_pointer(self.clone(), move |writer, value| {
+					value.write_args(writer, args)
+				}),
+		}
+	}
+}
+
+macro_rules! tuple_impl {
+	($($name: ident $index: tt),*) => {
+		impl<$($name: BinRead),*> BinRead for ($($name,)*) {
+			type Args = ($($name::Args,)*);
+
+			fn read_args<S: Read + Seek>(parser: &mut BinaryParser<S>, args: Self::Args) -> Result<Self> {
+				Ok(($($name::read_args(parser, args.$index)?,)*))
+			}
+		}
+
+		impl<$($name: BinWrite),*> BinWrite for ($($name,)*) {
+			type Args = ($($name::Args,)*);
+
+			fn write_args<S: Write + Seek>(
+				&self,
+				parser: &mut BinaryParser<S>,
+				args: Self::Args,
+			) -> Result<()> {
+				$(self.$index.write_args(parser, args.$index)?;)*
+				Ok(())
+			}
+		}
+	};
+}
+
+tuple_impl!(A 0);
+tuple_impl!(A 0, B 1);
+tuple_impl!(A 0, B 1, C 2);
+tuple_impl!(A 0, B 1, C 2, D 3);
+tuple_impl!(A 0, B 1, C 2, D 3, E 4);
+tuple_impl!(A 0, B 1, C 2, D 3, E 4, F 5);
+tuple_impl!(A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+tuple_impl!(A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn overflowing_pointer_is_out_of_bounds() {
+		let mut parser =
+			BinaryParser::from_buf([0; 4].into_iter().chain([0xFF; 8]).collect::<Vec<_>>());
+		parser.set_pointer_width(Width::U64);
+		parser.push_base(4);
+		parser.seek(SeekFrom::Start(4)).unwrap();
+		let err = parser.read_pointer(|reader| reader.read_u8()).unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::PointerOutOfBounds {
+				pointer: u64::MAX,
+				..
+			}
+		));
+		assert_eq!(err.position(), Some(4));
+	}
+
+	#[test]
+	fn sub_view_is_bounded_at_both_ends() {
+		let mut parser = BinaryParser::from_buf(vec![1, 2, 3, 4, 0]);
+		parser
+			.sub_view(2, 2, |view| {
+				assert_eq!(view.read_u8()?, 3);
+				assert!(view.seek(SeekFrom::Start(0)).is_err());
+				assert!(view.seek(SeekFrom::Current(-2)).is_err());
+				assert_eq!(view.position(), 3);
+				view.seek(SeekFrom::End(-2))?;
+				assert_eq!(view.read_u16()?, 0x0403);
+				assert!(view.read_u8().is_err());
+				Ok(())
+			})
+			.unwrap();
+		let mut parser = BinaryParser::from_buf(vec![1, 2, 3, 4, 0, 0]);
+		let options = PointerOptions::new().width(Width::U16);
+		let err = parser
+			.sub_view(4, 2, |view| {
+				view.read_pointer_with(options, |view| view.read_u8())
+			})
+			.unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::PointerOutOfBounds { pointer: 0, .. }
+		));
+	}
+
+	#[test]
+	fn sub_view_rejects_pending_writes() {
+		let mut parser = BinaryParser::from_buf(vec![0; 8]);
+		let err = parser
+			.sub_view(0, 8, |view| {
+				view.write_pointer(|writer| writer.write_u8(1))?;
+				view.reserve_u16()?;
+				Ok(())
+			})
+			.unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::PendingInView {
+				writes: 1,
+				slots: 1,
+				chunks: 0
+			}
+		));
+		assert!(!parser.pending_writes());
+
+		let res = parser.sub_view(0, 8, |view| {
+			let slot = view.reserve_u16()?;
+			view.fill(slot, 3)
+		});
+		assert!(res.is_ok());
+	}
+
+	#[test]
+	fn sub_view_tracks_visited_pointers() {
+		let mut parser = BinaryParser::from_buf(vec![0, 0, 0, 0, 8, 0, 0, 0, 7]);
+		parser.mark_pointers([4]);
+		let value = parser
+			.sub_view(4, 5, |view| {
+				assert_eq!(view.marked_pointers().collect::<Vec<_>>(), [4]);
+				view.read_pointer(|view| view.read_u8())
+			})
+			.unwrap();
+		assert_eq!(value, 7);
+		assert!(parser.unvisited_pointers().is_empty());
+	}
+
+	#[test]
+	fn chunks_read_in_place() {
+		let layout = ChunkLayout::new(4, Width::U32).align(4);
+		let mut parser = BinaryParser::new();
+		parser.begin_chunk_with(b"AAAA", layout).unwrap();
+		parser.write_u8(1).unwrap();
+		parser.end_chunk().unwrap();
+		parser.begin_chunk_with(b"BBBB", layout).unwrap();
+		parser.write_u16(2).unwrap();
+		parser.end_chunk().unwrap();
+		let mut parser = BinaryParser::from_buf(parser.to_buf().unwrap());
+
+		let mut chunks = parser.chunks(layout);
+		let first = chunks.next().unwrap().unwrap();
+		assert_eq!(
+			(&first.magic[..], first.offset, first.body),
+			(&b"AAAA"[..], 0, 8)
+		);
+		assert_eq!(chunks.view(&first, |view| view.read_u8()).unwrap(), 1);
+		let second = chunks.next().unwrap().unwrap();
+		assert_eq!(
+			(&second.magic[..], second.offset, second.size),
+			(&b"BBBB"[..], 12, 4)
+		);
+		let err = chunks
+			.view(&second, |view| {
+				view.read_u16()?;
+				view.read_u32()
+			})
+			.unwrap_err();
+		assert_eq!(err.position(), Some(22));
+		assert!(chunks.next().is_none());
+	}
+
+	#[test]
+	fn optional_pointers() {
+		let mut parser = BinaryParser::new();
+		parser
+			.write_optional_pointer(None, |writer, value| writer.write_u8(value))
+			.unwrap();
+		parser
+			.write_optional_pointer(Some(7), |writer, value| writer.write_u8(value))
+			.unwrap();
+		let mut parser = BinaryParser::from_buf(parser.to_buf().unwrap());
+		assert_eq!(
+			parser
+				.read_optional_pointer(|reader| reader.read_u8())
+				.unwrap(),
+			None
+		);
+		assert_eq!(
+			parser
+				.read_optional_pointer(|reader| reader.read_u8())
+				.unwrap(),
+			Some(7)
+		);
+	}
+
+	#[test]
+	fn option_args() {
+		let mut parser = BinaryParser::new();
+		let args = OptionArgs::Pointer(());
+		None::<u8>.write_args(&mut parser, args).unwrap();
+		Some(7u8).write_args(&mut parser, args).unwrap();
+		let mut parser = BinaryParser::from_buf(parser.to_buf().unwrap());
+		assert_eq!(Option::<u8>::read_args(&mut parser, args).unwrap(), None);
+		assert_eq!(Option::<u8>::read_args(&mut parser, args).unwrap(), Some(7));
+
+		let mut parser = BinaryParser::new();
+		Some(9u8).write(&mut parser).unwrap();
+		None::<u8>.write(&mut parser).unwrap();
+		let mut parser = BinaryParser::from_buf(parser.to_buf().unwrap());
+		assert_eq!(parser.read::<Option<u8>>().unwrap(), Some(9));
+		assert_eq!(parser.read::<Option<u8>>().unwrap(), None);
+	}
+
+	#[test]
+	fn pof0_round_trip() {
+		// Deltas of 0 and 8 take one byte, 0x100 two bytes and 0x10000 four bytes
+		let positions = [0, 8, 0x108, 0x10108];
+		let mut parser = BinaryParser::new();
+		parser.write_pof0(&positions).unwrap();
+		let buf = parser.to_buf().unwrap();
+		assert_eq!(buf, [12, 0, 0, 0, 0x40, 0x42, 0x80, 0x40, 0xC0, 0, 0x40, 0]);
+		let mut parser = BinaryParser::from_buf(buf);
+		assert_eq!(parser.read_pof0().unwrap(), positions);
+		assert_eq!(parser.position(), 12);
+
+		let mut parser = BinaryParser::from_buf(vec![0, 0, 0, 0, 0x41]);
+		let err = parser.read_pof0().unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::InvalidValue { .. }
+		));
+		assert_eq!(err.position(), Some(0));
+
+		let mut parser = BinaryParser::new();
+		parser.set_relocation_table(RelocationTable::Pof0);
+		parser.write_pointer(|writer| writer.write_u32(1)).unwrap();
+		parser.write_null_pointer().unwrap();
+		parser
+			.write_pointer(|writer| {
+				writer.write_buf(&[0; 0x100])?;
+				writer.write_pointer(|writer| writer.write_u8(9))
+			})
+			.unwrap();
+		let parser = parser.finish_writes().unwrap();
+		let relocations = parser.relocations().to_vec();
+		assert_eq!(relocations, [0, 8, 0x110]);
+		let table = parser.len() - 8;
+		let mut parser = BinaryParser::from_buf(parser.to_buf().unwrap());
+		parser.seek(SeekFrom::Start(table)).unwrap();
+		assert_eq!(parser.read_pof0().unwrap(), relocations);
+	}
+
+	#[test]
+	fn write_orders() {
+		let layout = |order| {
+			let mut parser = BinaryParser::new();
+			parser.set_write_order(order);
+			parser.set_pointer_width(Width::U16);
+			parser
+				.write_pointer(|writer| {
+					writer.write_u8(0xA)?;
+					writer.write_pointer(|writer| {
+						writer.write_u8(0xAA)?;
+						writer.write_pointer(|writer| writer.write_u8(0xAB))
+					})
+				})
+				.unwrap();
+			parser
+				.write_pointer(|writer| {
+					writer.write_u8(0xB)?;
+					writer.write_pointer(|writer| writer.write_u8(0xBA))
+				})
+				.unwrap();
+			parser
+				.write_pointer_with(PointerOptions::new().priority(1), |writer| {
+					writer.write_u8(0xC)
+				})
+				.unwrap();
+			parser.to_buf().unwrap()[6..].to_vec()
+		};
+		assert_eq!(
+			layout(WriteOrder::DepthFirst),
+			[0xC, 0xA, 0xA, 0, 0xAA, 0xD, 0, 0xAB, 0xB, 0x11, 0, 0xBA]
+		);
+		assert_eq!(
+			layout(WriteOrder::BreadthFirst),
+			[0xC, 0xA, 0xD, 0, 0xB, 0x10, 0, 0xAA, 0x11, 0, 0xBA, 0xAB]
+		);
+	}
+
+	#[test]
+	fn dedup_of_last_payload() {
+		let mut parser = BinaryParser::new();
+		parser.write_u8(1).unwrap();
+		parser
+			.with_base(1, |parser| {
+				parser.write_pointer_aligned(8, |writer| writer.write_u8(0xA))?;
+				let options = PointerOptions::new().align(4, 0xFF).dedup();
+				parser.write_pointer_with(options.clone(), |writer| writer.write_u8(0xA))?;
+				parser.write_pointer_with(options, |writer| writer.write_u8(0xA))
+			})
+			.unwrap();
+		// The duplicate and its alignment padding are cut from the end of the output
+		assert_eq!(
+			parser.to_buf().unwrap(),
+			[
+				1, 0x10, 0, 0, 0, 0x14, 0, 0, 0, 0x14, 0, 0, 0, 0, 0, 0, 0, 0xA, 0xFF, 0xFF, 0xFF,
+				0xA
+			]
+		);
+	}
+
+	#[test]
+	fn slots_are_backpatched() {
+		let mut parser = BinaryParser::new();
+		parser.set_big_endian(true);
+		let count = parser.reserve_u16().unwrap();
+		let size = parser.reserve_u32().unwrap();
+		parser.write_buf(&[1, 2, 3]).unwrap();
+		parser.fill(count, 3).unwrap();
+		parser.fill_with_length_since(size).unwrap();
+		assert_eq!(parser.to_buf().unwrap(), [0, 3, 0, 0, 0, 3, 1, 2, 3]);
+
+		let mut parser = BinaryParser::new();
+		parser.reserve_u64().unwrap();
+		let err = parser.to_buf().unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::UnfilledSlot { width: Width::U64 }
+		));
+		assert_eq!(err.position(), Some(0));
+	}
+
+	#[test]
+	fn nested_chunks() {
+		let mut parser = BinaryParser::new();
+		parser.begin_chunk(b"RIFF", Width::U32).unwrap();
+		parser.write_buf(b"WAVE").unwrap();
+		let layout = ChunkLayout::new(4, Width::U16)
+			.size_includes_header(true)
+			.align(4);
+		parser.begin_chunk_with(b"POF0", layout).unwrap();
+		parser.write_u8(1).unwrap();
+		parser.end_chunk().unwrap();
+		parser.end_chunk().unwrap();
+		assert_eq!(
+			parser.to_buf().unwrap(),
+			b"RIFF\x0c\0\0\0WAVEPOF0\x08\0\x01\0"
+		);
+
+		let mut parser = BinaryParser::new();
+		let err = parser.end_chunk().unwrap_err();
+		assert!(matches!(err.inner(), BinaryParserError::MissingChunk));
+
+		let mut parser = BinaryParser::new();
+		parser.write_u32(0).unwrap();
+		parser.begin_chunk_with(b"POF0", layout).unwrap();
+		parser.seek(SeekFrom::Start(0)).unwrap();
+		let err = parser.end_chunk().unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::InvalidValue { .. }
+		));
+		assert_eq!(err.position(), Some(4));
+	}
+
+	#[test]
+	fn deferred_writes_keep_their_endian() {
+		let mut parser = BinaryParser::new();
+		parser
+			.with_endian(Endian::Big, |parser| {
+				parser.write_pointer(|writer| writer.write_u32(1))?;
+				parser.reserve_u16()
+			})
+			.and_then(|slot| parser.fill(slot, 2))
+			.unwrap();
+		assert_eq!(parser.to_buf().unwrap(), [0, 0, 0, 6, 0, 2, 0, 0, 0, 1]);
+	}
+
+	#[test]
+	fn dedup_respects_alignment() {
+		let mut parser = BinaryParser::new();
+		parser.write_u8(0).unwrap();
+		parser
+			.write_pointer_dedup(|writer| writer.write_u8(0xA))
+			.unwrap();
+		let options = PointerOptions::new().dedup().align(16, 0);
+		parser
+			.write_pointer_with(options, |writer| writer.write_u8(0xA))
+			.unwrap();
+		let buf = parser.to_buf().unwrap();
+		assert_eq!(buf[1..9], [9, 0, 0, 0, 0x10, 0, 0, 0]);
+		assert_eq!(buf[9..], [0xA, 0, 0, 0, 0, 0, 0, 0xA]);
+	}
+
+	#[test]
+	fn dedup_keeps_duplicates_it_cannot_truncate() {
+		let mut parser = BinaryParser::from_stream(Cursor::new(vec![])).unwrap();
+		parser
+			.write_pointer_dedup(|writer| writer.write_u16(1))
+			.unwrap();
+		parser
+			.write_pointer_dedup(|writer| writer.write_u16(1))
+			.unwrap();
+		let parser = parser.finish_writes().unwrap();
+		assert_eq!(parser.len(), 12);
+		assert_eq!(
+			parser.inner.into_inner(),
+			[8, 0, 0, 0, 10, 0, 0, 0, 1, 0, 1, 0]
+		);
+	}
+
+	#[test]
+	fn detect_endian_skips_failing_orders() {
+		let mut parser = BinaryParser::from_buf(vec![0, 0, 0, 1]);
+		let endian = parser.detect_endian_by(|reader| match reader.read_u32()? {
+			value if value < 0x100 => Ok(true),
+			value => Err(reader.error(BinaryParserError::invalid_value("count", value))),
+		});
+		assert_eq!(endian.unwrap(), Endian::Big);
+		assert_eq!(parser.position(), 0);
+
+		let mut parser = BinaryParser::from_buf(vec![1, 0, 0, 1]);
+		let err = parser
+			.detect_endian_by(|reader| match reader.read_u32()? {
+				value if value < 0x100 => Ok(true),
+				value => Err(reader.error(BinaryParserError::invalid_value("count", value))),
+			})
+			.unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::InvalidValue { .. }
+		));
+	}
+
+	#[test]
+	fn relocation_list_overflow() {
+		let mut parser = BinaryParser::new();
+		parser.set_relocation_table(RelocationTable::U32List);
+		parser.relocations.push(0x1_0000_0000);
+		let err = parser.to_buf().unwrap_err();
+		assert!(matches!(
+			err.inner(),
+			BinaryParserError::ValueOverflow {
+				value: 0x1_0000_0000,
+				width: Width::U32
+			}
+		));
+	}
+
+	#[test]
+	fn merged_child_ending_in_pointer() {
+		let mut child = BinaryParser::new();
+		child.write_u32(0xDEAD).unwrap();
+		child.write_pointer(|writer| writer.write_u8(0x42)).unwrap();
+		assert_eq!(child.len(), 8);
+
+		let mut parser = BinaryParser::new();
+		parser.write_buf(&[0; 8]).unwrap();
+		parser.write_parser_merged(child).unwrap();
+		let buf = parser.to_buf().unwrap();
+		assert_eq!(buf[8..], [0xAD, 0xDE, 0, 0, 0x10, 0, 0, 0, 0x42]);
+	}
+
+	#[test]
+	fn merged_child_shares_string_pool() {
+		let mut child = BinaryParser::new();
+		child.write_null_string_pointer("ab").unwrap();
+
+		let mut parser = BinaryParser::new();
+		parser.set_string_pool(StringPool::Trailing);
+		parser.write_null_string_pointer("ab").unwrap();
+		parser.write_parser_merged(child).unwrap();
+		assert_eq!(
+			parser.to_buf().unwrap(),
+			[8, 0, 0, 0, 8, 0, 0, 0, b'a', b'b', 0]
+		);
 	}
 }
@@ -0,0 +1,144 @@
+use std::borrow::Cow;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Width {
+	U16,
+	#[default]
+	U32,
+	U64,
+}
+
+impl Width {
+	pub fn bytes(self) -> u64 {
+		match self {
+			Self::U16 => 2,
+			Self::U32 => 4,
+			Self::U64 => 8,
+		}
+	}
+
+	pub fn max_value(self) -> u64 {
+		match self {
+			Self::U16 => u16::MAX as u64,
+			Self::U32 => u32::MAX as u64,
+			Self::U64 => u64::MAX,
+		}
+	}
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum RelativeTo {
+	// The innermost base pushed with push_base, or the start of the stream
+	#[default]
+	Start,
+	// The position of the pointer itself
+	Field,
+	// The innermost position pushed with push_marker
+	Marker,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum StringPool {
+	#[default]
+	Disabled,
+	// Each distinct string is written where its first pointer would have placed it
+	Inline,
+	// Distinct strings are written after all other pointer targets
+	Trailing,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum WriteOrder {
+	// Targets scheduled by a payload are written after all of its pending siblings
+	#[default]
+	BreadthFirst,
+	// Targets scheduled by a payload are written directly after it
+	DepthFirst,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RelocationTable {
+	// Sega POF0, a u32 length followed by 4 byte deltas packed into 1, 2 or 4 bytes, padded to 4
+	Pof0,
+	// Every pointer position as a u32
+	U32List,
+}
+
+// Per call overrides for the pointer APIs, unset fields fall back to the parser settings
+#[derive(Clone, Debug, Default)]
+pub struct PointerOptions {
+	pub(crate) width: Option<Width>,
+	pub(crate) relative_to: Option<RelativeTo>,
+	pub(crate) offset: u64,
+	pub(crate) null: Option<u64>,
+	pub(crate) dedup: Option<Dedup>,
+	pub(crate) section: Option<Cow<'static, str>>,
+	pub(crate) priority: i32,
+	pub(crate) align: Option<(u64, u8)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Dedup {
+	// Keyed by the bytes the payload writes, known up front for pooled strings
+	Output(Option<Vec<u8>>),
+	Key(Vec<u8>),
+}
+
+impl PointerOptions {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn width(mut self, width: Width) -> Self {
+		self.width = Some(width);
+		self
+	}
+
+	pub fn relative_to(mut self, relative_to: RelativeTo) -> Self {
+		self.relative_to = Some(relative_to);
+		self
+	}
+
+	// Added to the pointer when reading and subtracted when writing
+	pub fn offset(mut self, offset: u64) -> Self {
+		self.offset = offset;
+		self
+	}
+
+	// Targets writing identical bytes are emitted once and shared.
+	// Payloads that schedule pointers of their own are never merged
+	pub fn dedup(mut self) -> Self {
+		self.dedup = Some(Dedup::Output(None));
+		self
+	}
+
+	// Targets with the same key are emitted once and shared
+	pub fn dedup_key<K: AsRef<[u8]>>(mut self, key: K) -> Self {
+		self.dedup = Some(Dedup::Key(key.as_ref().to_vec()));
+		self
+	}
+
+	// Places the target in a named section, see BinaryParser::define_section
+	pub fn section<N: Into<Cow<'static, str>>>(mut self, name: N) -> Self {
+		self.section = Some(name.into());
+		self
+	}
+
+	// Targets with a higher priority are written first within their section, ties keep the write order
+	pub fn priority(mut self, priority: i32) -> Self {
+		self.priority = priority;
+		self
+	}
+
+	// Pads with pad until the target is aligned, relative to the base it was scheduled under
+	pub fn align(mut self, alignment: u64, pad: u8) -> Self {
+		self.align = Some((alignment, pad));
+		self
+	}
+
+	// Raw value marking an absent target for the optional pointer APIs
+	pub fn null(mut self, null: u64) -> Self {
+		self.null = Some(null);
+		self
+	}
+}
@@ -0,0 +1,125 @@
+use binary_parser::{BinaryParser, BinaryParserError, Endian};
+use binary_parser_derive::{BinRead, BinWrite};
+use std::fmt::Debug;
+
+fn round_trip<T>(value: &T, endian: Endian) -> Vec<u8>
+where
+	T: binary_parser::BinRead<Args = ()> + binary_parser::BinWrite<Args = ()> + PartialEq + Debug,
+{
+	let mut parser = BinaryParser::new();
+	parser.set_endian(endian);
+	parser.write(value).unwrap();
+	let buf = parser.to_buf().unwrap();
+	let mut parser = BinaryParser::from_buf(buf.clone());
+	parser.set_endian(endian);
+	assert_eq!(&parser.read::<T>().unwrap(), value);
+	buf
+}
+
+#[derive(BinRead, BinWrite, Clone, Debug, PartialEq)]
+struct Inner {
+	#[bin(pointer)]
+	value: u32,
+}
+
+#[derive(BinRead, BinWrite, Debug, PartialEq)]
+#[bin(big)]
+struct Outer {
+	inner: Inner,
+}
+
+#[derive(BinRead, BinWrite, Debug, PartialEq)]
+struct BigPointer {
+	#[bin(big, pointer)]
+	value: u32,
+	tail: u16,
+}
+
+#[test]
+fn pointers_keep_the_endian_of_their_struct() {
+	let outer = Outer {
+		inner: Inner { value: 1 },
+	};
+	assert_eq!(round_trip(&outer, Endian::Little), [0, 0, 0, 4, 0, 0, 0, 1]);
+
+	let value = BigPointer { value: 1, tail: 2 };
+	assert_eq!(
+		round_trip(&value, Endian::Little),
+		[0, 0, 0, 6, 2, 0, 0, 0, 0, 1]
+	);
+}
+
+#[derive(BinRead, BinWrite, Debug, PartialEq)]
+#[bin(magic = b"HDR\0")]
+struct Header {
+	count: u8,
+	#[bin(count = count)]
+	values: Vec<u16>,
+	#[bin(pointer)]
+	name: String,
+	#[bin(pointer)]
+	child: Option<Inner>,
+	#[bin(pointer)]
+	missing: Option<u8>,
+	#[bin(align = 4)]
+	kind: Kind,
+}
+
+#[derive(BinRead, BinWrite, Debug, PartialEq)]
+#[bin(repr = u8)]
+enum Kind {
+	Empty,
+	Value(u16),
+	#[bin(id = 7)]
+	Named {
+		#[bin(little)]
+		value: u16,
+	},
+}
+
+#[test]
+fn structs_and_enums_round_trip() {
+	for kind in [Kind::Empty, Kind::Value(3), Kind::Named { value: 4 }] {
+		let header = Header {
+			count: 2,
+			values: vec![1, 2],
+			name: "ab".to_string(),
+			child: Some(Inner { value: 5 }),
+			missing: None,
+			kind,
+		};
+		round_trip(&header, Endian::Little);
+		round_trip(&header, Endian::Big);
+	}
+}
+
+#[test]
+fn bad_magic_and_enum_ids_are_errors() {
+	let mut parser = BinaryParser::from_buf(b"HDX\0".to_vec());
+	assert!(parser.read::<Header>().is_err());
+
+	let mut parser = BinaryParser::from_buf(vec![2]);
+	let err = parser.read::<Kind>().unwrap_err();
+	assert!(matches!(
+		err.inner(),
+		BinaryParserError::InvalidValue { .. }
+	));
+	assert_eq!(err.position(), Some(0));
+}
+
+#[derive(BinRead, BinWrite, Debug, PartialEq)]
+struct Generic<T> {
+	#[bin(pointer)]
+	value: T,
+	#[bin(pointer)]
+	optional: Option<T>,
+}
+
+#[test]
+fn generic_pointer_fields() {
+	let value = Generic {
+		value: 1u16,
+		optional: Some(2),
+	};
+	round_trip(&value, Endian::Little);
+}